@@ -0,0 +1,203 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::instruction::Signer;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::sysvars::rent::Rent;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, seeds};
+use shank::ShankAccount;
+
+use crate::utils::{check_owner, find_program_address};
+
+#[derive(Clone, ShankAccount)]
+pub struct BuyerRecord {
+    pub sale_address: Pubkey,
+    pub buyer_address: Pubkey,
+    // Amount bought, before any transfer fee; the unit of `total_sold`.
+    pub purchased: u64,
+    // Part of `purchased` that reached the buyer at purchase time, after the
+    // transfer fee. Vested purchases are delivered on claim instead.
+    pub received: u64,
+    pub paid: u64,
+    // Protocol fees held in the vault for this buyer; see
+    // `FlashSale::holds_fees`. Refunded together with `paid`.
+    pub protocol_fees: u64,
+    // Referral rewards paid out of this buyer's purchases. They stay in the
+    // vault until the sale unlocks its proceeds, so refunds return them too.
+    pub referral_fees: u64,
+    pub claimed: u64,
+    // Wallet that funded the purchases, which is where refunds go. Set when
+    // the record is created; see `get_token` for payers buying for others.
+    pub payer_address: Pubkey,
+}
+
+pub const BUYER_RECORD_ACCOUNT_SIZE: usize = 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 32;
+
+impl TryFrom<&[u8]> for BuyerRecord {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < BUYER_RECORD_ACCOUNT_SIZE {
+            return Err("Buyer record too small".to_string());
+        }
+        let sale_address: Pubkey = data[0..32].try_into().unwrap();
+        let buyer_address: Pubkey = data[32..64].try_into().unwrap();
+        let purchased = u64::from_le_bytes(data[64..72].try_into().unwrap());
+        let received = u64::from_le_bytes(data[72..80].try_into().unwrap());
+        let paid = u64::from_le_bytes(data[80..88].try_into().unwrap());
+        let protocol_fees = u64::from_le_bytes(data[88..96].try_into().unwrap());
+        let referral_fees = u64::from_le_bytes(data[96..104].try_into().unwrap());
+        let claimed = u64::from_le_bytes(data[104..112].try_into().unwrap());
+        let payer_address: Pubkey = data[112..144].try_into().unwrap();
+
+        Ok(BuyerRecord {
+            sale_address,
+            buyer_address,
+            purchased,
+            received,
+            paid,
+            protocol_fees,
+            referral_fees,
+            claimed,
+            payer_address,
+        })
+    }
+}
+
+impl BuyerRecord {
+    pub fn write_to_slice(&self, buf: &mut [u8]) -> Result<(), ProgramError> {
+        buf[0..32].copy_from_slice(&self.sale_address);
+        buf[32..64].copy_from_slice(&self.buyer_address);
+        buf[64..72].copy_from_slice(&self.purchased.to_le_bytes());
+        buf[72..80].copy_from_slice(&self.received.to_le_bytes());
+        buf[80..88].copy_from_slice(&self.paid.to_le_bytes());
+        buf[88..96].copy_from_slice(&self.protocol_fees.to_le_bytes());
+        buf[96..104].copy_from_slice(&self.referral_fees.to_le_bytes());
+        buf[104..112].copy_from_slice(&self.claimed.to_le_bytes());
+        buf[112..144].copy_from_slice(&self.payer_address);
+
+        Ok(())
+    }
+}
+
+pub fn find_buyer_record(
+    buyer: &Pubkey,
+    buyer_record_pda: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let expected_record_account = find_program_address(
+        &[b"buyer", flash_sale_pda.key(), buyer],
+        &crate::id(),
+    );
+    if expected_record_account.0 != *buyer_record_pda.key() {
+        msg!("Unexpected buyer record address");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(expected_record_account.1)
+}
+
+pub fn load_buyer_record(
+    buyer: &Pubkey,
+    buyer_record_pda: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+) -> Result<BuyerRecord, ProgramError> {
+    find_buyer_record(buyer, buyer_record_pda, flash_sale_pda)?;
+    check_owner(buyer_record_pda, crate::id())?;
+
+    let data = buyer_record_pda.try_borrow_data()?;
+    BuyerRecord::try_from(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+pub fn load_or_create_buyer_record(
+    payer: &AccountInfo,
+    buyer: &Pubkey,
+    buyer_record_pda: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+) -> Result<BuyerRecord, ProgramError> {
+    if buyer_record_pda.owner() == &crate::id() {
+        return load_buyer_record(buyer, buyer_record_pda, flash_sale_pda);
+    }
+
+    let record_bump = find_buyer_record(buyer, buyer_record_pda, flash_sale_pda)?;
+    let record_binding = [record_bump];
+    let record_seeds = seeds!(
+        b"buyer",
+        flash_sale_pda.key(),
+        buyer,
+        &record_binding
+    );
+
+    let rent = Rent::get()?;
+    pinocchio_system::instructions::CreateAccount {
+        from: payer,
+        to: buyer_record_pda,
+        space: BUYER_RECORD_ACCOUNT_SIZE as u64,
+        lamports: rent.minimum_balance(BUYER_RECORD_ACCOUNT_SIZE),
+        owner: &crate::id(),
+    }
+    .invoke_signed(&[Signer::from(&record_seeds)])?;
+
+    Ok(BuyerRecord {
+        sale_address: *flash_sale_pda.key(),
+        buyer_address: *buyer,
+        purchased: 0,
+        received: 0,
+        paid: 0,
+        protocol_fees: 0,
+        referral_fees: 0,
+        claimed: 0,
+        payer_address: *payer.key(),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // An empty record of `buyer` in `sale`.
+    pub fn record(sale: Pubkey, buyer: Pubkey) -> BuyerRecord {
+        BuyerRecord {
+            sale_address: sale,
+            buyer_address: buyer,
+            purchased: 0,
+            received: 0,
+            paid: 0,
+            protocol_fees: 0,
+            referral_fees: 0,
+            claimed: 0,
+            payer_address: buyer,
+        }
+    }
+
+    pub fn record_data(record: &BuyerRecord) -> Vec<u8> {
+        let mut data = vec![0; BUYER_RECORD_ACCOUNT_SIZE];
+        record.write_to_slice(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn buyer_record_round_trips() {
+        let record = BuyerRecord {
+            sale_address: [1; 32],
+            buyer_address: [2; 32],
+            purchased: 1_000,
+            received: 990,
+            paid: 500,
+            protocol_fees: 5,
+            referral_fees: 10,
+            claimed: 0,
+            payer_address: [3; 32],
+        };
+        let mut data = [0; BUYER_RECORD_ACCOUNT_SIZE];
+        record.write_to_slice(&mut data).unwrap();
+
+        let decoded = BuyerRecord::try_from(data.as_slice()).unwrap();
+        assert_eq!(decoded.purchased, 1_000);
+        assert_eq!(decoded.received, 990);
+        assert_eq!(decoded.paid, 500);
+        assert_eq!(decoded.protocol_fees, 5);
+        assert_eq!(decoded.referral_fees, 10);
+        assert_eq!(decoded.payer_address, [3; 32]);
+        assert!(BuyerRecord::try_from(&data[..BUYER_RECORD_ACCOUNT_SIZE - 1]).is_err());
+    }
+}
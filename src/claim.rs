@@ -0,0 +1,336 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::instruction::Signer;
+use pinocchio::program_error::ProgramError;
+use pinocchio::sysvars::clock::Clock;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, seeds, ProgramResult};
+
+use crate::buyer_record::load_buyer_record;
+use crate::commit::{settle_commitment, SettlementAccounts};
+use crate::error::FlashSaleError;
+use crate::events::{emit_withdrawal, WithdrawalKind};
+use crate::init_flash_sale::FlashSale;
+use crate::proceeds_vault::PaymentTokenAccounts;
+use crate::utils::{
+    check_address_is_any, check_associated_token_account, check_owner, check_token_account_owner, deinit_account_if_exists,
+    find_program_address, get_mint_decimals, transfer_tokens,
+};
+
+pub fn claim(accounts: &[AccountInfo], _instruction_data: &[u8]) -> ProgramResult {
+    claim_at(accounts, Clock::get()?.unix_timestamp)
+}
+
+fn claim_at(accounts: &[AccountInfo], now: i64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let buyer = accounts_iter.next().unwrap();
+    let buyer_record_pda = accounts_iter.next().unwrap();
+    let buyer_token_account = accounts_iter.next().unwrap();
+    let token_mint = accounts_iter.next().unwrap();
+    let token_deposit_pda = accounts_iter.next().unwrap();
+    let token_deposit_ata = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+    let token_program = accounts_iter.next().unwrap();
+
+    check_address_is_any(token_program, &[pinocchio_token::id(), spl_token_2022::id().to_bytes()])?;
+    check_owner(flash_sale_pda, crate::id())?;
+    check_token_account_owner(buyer_token_account, buyer.key())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.mint_address != *token_mint.key(){
+        msg!("Unexpected token mint address");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if !flash_sale_settings.vests() && !flash_sale_settings.commit_mode {
+        msg!("Sale delivers tokens at purchase");
+        return Err(ProgramError::InvalidArgument);
+    }
+    // Anyone can claim vested tokens or settle a commitment, so a buyer who
+    // never claims cannot keep the sale from closing. Tokens and refunds
+    // claimed on the buyer's behalf go to its associated token accounts.
+    let on_behalf = !buyer.is_signer();
+    if on_behalf {
+        check_associated_token_account(buyer_token_account, buyer.key(), token_mint, token_program)?;
+    }
+
+    if flash_sale_settings.soft_cap_failed(now) {
+        msg!("Soft cap was missed, ask for a refund instead");
+        return Err(FlashSaleError::RefundUnavailable.into());
+    }
+
+    let mut buyer_record = load_buyer_record(buyer.key(), buyer_record_pda, flash_sale_pda)?;
+
+    // Commit sales pass the settlement accounts ahead of any hook accounts.
+    let settlement_accounts = if flash_sale_settings.commit_mode {
+        Some(SettlementAccounts {
+            proceeds_vault_pda: accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?,
+            config_pda: accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?,
+            fee_recipient: accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?,
+            buyer_payment_account: accounts_iter.next(),
+            payment_accounts: PaymentTokenAccounts::from_iter(&flash_sale_settings, accounts_iter)?,
+        })
+    } else {
+        None
+    };
+    let hook_accounts = accounts_iter.as_slice();
+    if let (Some(settlement_accounts), true) = (settlement_accounts.as_ref(), on_behalf) {
+        if let Some(payment_accounts) = settlement_accounts.payment_accounts.as_ref() {
+            check_associated_token_account(
+                settlement_accounts.buyer_payment_account.ok_or(ProgramError::NotEnoughAccountKeys)?,
+                buyer.key(),
+                payment_accounts.payment_mint,
+                payment_accounts.payment_token_program,
+            )?;
+        }
+    }
+
+    // A commitment has nothing purchased until its first claim settles it.
+    let settled = flash_sale_settings.commit_mode && buyer_record.purchased == 0;
+    if let (Some(settlement_accounts), true) = (settlement_accounts.as_ref(), settled) {
+        let allocated = settle_commitment(
+            buyer,
+            flash_sale_pda,
+            &mut flash_sale_settings,
+            &mut buyer_record,
+            settlement_accounts,
+            now,
+        )?;
+        if !allocated {
+            flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+            return deinit_account_if_exists(buyer_record_pda, buyer);
+        }
+    }
+
+    let available = flash_sale_settings
+        .vested_amount(buyer_record.purchased, now)
+        .saturating_sub(buyer_record.claimed);
+    if available == 0 && !settled {
+        msg!("Nothing to claim");
+        return Err(FlashSaleError::NothingToClaim.into());
+    }
+
+    buyer_record.claimed += available;
+    buyer_record.write_to_slice(&mut buyer_record_pda.try_borrow_mut_data()?)?;
+
+    flash_sale_settings.total_claimed = flash_sale_settings
+        .total_claimed
+        .checked_add(available)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+    if available == 0 {
+        return Ok(());
+    }
+
+    let seed = flash_sale_settings.seed();
+    let id_seed = flash_sale_settings.id_seed();
+    let expected_deposit_account = find_program_address(
+        &[b"deposit", &seed, token_mint.key(), &flash_sale_settings.owner_address, &id_seed],
+        &crate::id(),
+    );
+    check_token_account_owner(token_deposit_ata, &expected_deposit_account.0)?;
+
+    let deposit_binding = [expected_deposit_account.1];
+    let deposit_seeds = seeds!(
+        b"deposit",
+        seed.as_slice(),
+        token_mint.key(),
+        &flash_sale_settings.owner_address,
+        id_seed.as_slice(),
+        &deposit_binding
+    );
+
+    transfer_tokens(
+        token_program,
+        token_deposit_ata,
+        token_mint,
+        buyer_token_account,
+        token_deposit_pda,
+        available,
+        get_mint_decimals(token_mint)?,
+        hook_accounts,
+        &[Signer::from(&deposit_seeds)],
+    )?;
+
+    emit_withdrawal(flash_sale_pda.key(), WithdrawalKind::VestedTokens, buyer_token_account.key(), available, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buyer_record::tests::{record, record_data};
+    use crate::buyer_record::BuyerRecord;
+    use crate::init_flash_sale::tests::{sale, sale_data};
+    use crate::utils::tests::{mint_data, program_address, test_accounts, token_account_data, TestAccount};
+    use pinocchio::pubkey::Pubkey;
+
+    const SALE: Pubkey = [5; 32];
+    const BUYER: Pubkey = [7; 32];
+
+    // A SOL commit sale that ended at 2_000 with 40_000 committed for its
+    // 1_000 tokens, 10_000 of it by BUYER.
+    fn commit_sale() -> FlashSale {
+        FlashSale { commit_mode: true, total_sold: 0, total_committed: 40_000, ..sale("Ticket") }
+    }
+
+    // A sale that ended at 2_000 and releases its tokens from 2_100 to 2_400.
+    fn vesting_sale() -> FlashSale {
+        FlashSale { vesting_cliff: 100, vesting_duration: 400, ..sale("Ticket") }
+    }
+
+    fn bought(purchased: u64) -> BuyerRecord {
+        BuyerRecord { purchased, paid: purchased * 10, ..record(SALE, BUYER) }
+    }
+
+    fn committed(paid: u64) -> BuyerRecord {
+        BuyerRecord { paid, ..record(SALE, BUYER) }
+    }
+
+    // Claim accounts for BUYER, with the settlement accounts of a SOL sale.
+    fn claim_accounts(sale: &FlashSale, record: &BuyerRecord, buyer_token_account: Pubkey) -> Vec<TestAccount> {
+        let token_program = pinocchio_token::id();
+        let mint = sale.mint_address;
+        let deposit = program_address(&[b"deposit", &sale.seed(), &mint, &sale.owner_address, &sale.id_seed()]);
+        vec![
+            TestAccount::wallet(BUYER),
+            TestAccount::new(program_address(&[b"buyer", &SALE, &BUYER]), crate::id(), record_data(record)),
+            TestAccount::new(buyer_token_account, token_program, token_account_data(&mint, &BUYER, 0)),
+            TestAccount::new(mint, token_program, mint_data(None, 0)),
+            TestAccount::wallet(deposit),
+            TestAccount::new([8; 32], token_program, token_account_data(&mint, &deposit, 1_000)),
+            TestAccount::new(SALE, crate::id(), sale_data(sale)),
+            TestAccount::new(token_program, [0; 32], Vec::new()),
+            TestAccount::new(program_address(&[b"proceeds", &SALE]), crate::id(), Vec::new()),
+            TestAccount::wallet(program_address(&[b"config"])),
+            TestAccount::wallet([9; 32]),
+            TestAccount::wallet(crate::id()),
+        ]
+    }
+
+    fn associated_token_account(wallet: &Pubkey, mint: &Pubkey) -> Pubkey {
+        find_program_address(
+            &[wallet, &pinocchio_token::id(), mint],
+            &pinocchio_associated_token_account::id(),
+        )
+        .0
+    }
+
+    #[test]
+    fn anyone_settles_a_commitment_once_the_sale_ends() {
+        let sale = commit_sale();
+        let ata = associated_token_account(&BUYER, &sale.mint_address);
+        let accounts = test_accounts(&claim_accounts(&sale, &committed(10_000), ata));
+        let accounts = &accounts.infos;
+
+        assert_eq!(claim_at(accounts, 1_500).err(), Some(ProgramError::InvalidArgument));
+
+        claim_at(accounts, 2_500).unwrap();
+        // 250 tokens for 2_500 of the 10_000 committed, the rest goes back.
+        let settled = FlashSale::try_from(accounts[6].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!((settled.commit_settled, settled.total_sold, settled.total_claimed), (10_000, 250, 250));
+        let record = BuyerRecord::try_from(accounts[1].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!((record.purchased, record.paid, record.claimed), (250, 2_500, 250));
+        assert_eq!(accounts[0].lamports(), 1_000_007_500);
+        assert_eq!(accounts[8].lamports(), 999_992_500);
+
+        assert_eq!(claim_at(accounts, 2_600).err(), Some(FlashSaleError::NothingToClaim.into()));
+    }
+
+    #[test]
+    fn settling_on_behalf_delivers_to_the_associated_token_account() {
+        let sale = commit_sale();
+        let accounts = test_accounts(&claim_accounts(&sale, &committed(10_000), [4; 32]));
+        assert_eq!(claim_at(&accounts.infos, 2_500).err(), Some(ProgramError::InvalidSeeds));
+
+        // The buyer itself may claim into any account it owns.
+        let mut signed = claim_accounts(&sale, &committed(10_000), [4; 32]);
+        signed[0] = TestAccount::wallet(BUYER).signer();
+        claim_at(&test_accounts(&signed).infos, 2_500).unwrap();
+    }
+
+    #[test]
+    fn vesting_starts_at_the_end_of_the_sale_after_the_cliff() {
+        let sale = vesting_sale();
+        assert_eq!(sale.vested_amount(1_000, 1_500), 0);
+        assert_eq!(sale.vested_amount(1_000, 2_099), 0);
+        assert_eq!(sale.vested_amount(1_000, 2_100), 250);
+        assert_eq!(sale.vested_amount(1_000, 2_300), 750);
+        assert_eq!(sale.vested_amount(1_000, 2_400), 1_000);
+        assert_eq!(sale.vested_amount(1_000, i64::MAX), 1_000);
+    }
+
+    #[test]
+    fn vested_purchases_count_against_the_supply() {
+        let sale = FlashSale {
+            vesting_duration: 100,
+            total_supply: 1_000,
+            total_sold: 1_000,
+            ..sale("Ticket")
+        };
+        assert_eq!(sale.remaining_supply(), 0);
+        assert_eq!(FlashSale { total_sold: 1_200, ..sale }.remaining_supply(), 0);
+    }
+
+    #[test]
+    fn anyone_claims_vested_tokens_into_the_associated_token_account() {
+        let sale = vesting_sale();
+        let ata = associated_token_account(&BUYER, &sale.mint_address);
+        let mut accounts = claim_accounts(&sale, &bought(100), ata);
+        accounts.truncate(8);
+        let accounts = test_accounts(&accounts);
+        let accounts = &accounts.infos;
+
+        assert_eq!(claim_at(accounts, 2_099).err(), Some(FlashSaleError::NothingToClaim.into()));
+        claim_at(accounts, 2_100).unwrap();
+        claim_at(accounts, 2_300).unwrap();
+        let record = BuyerRecord::try_from(accounts[1].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!(record.claimed, 75);
+        let claimed = FlashSale::try_from(accounts[6].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!(claimed.total_claimed, 75);
+        assert_eq!(claim_at(accounts, 2_300).err(), Some(FlashSaleError::NothingToClaim.into()));
+
+        let mut elsewhere = claim_accounts(&sale, &bought(100), [4; 32]);
+        elsewhere.truncate(8);
+        assert_eq!(claim_at(&test_accounts(&elsewhere).infos, 2_300).err(), Some(ProgramError::InvalidSeeds));
+    }
+
+    #[test]
+    fn claims_check_the_sale_and_its_accounts() {
+        let run = |accounts: &mut Vec<TestAccount>| {
+            accounts[0] = TestAccount::wallet(BUYER).signer();
+            accounts.truncate(8);
+            claim_at(&test_accounts(accounts).infos, 2_500).err()
+        };
+        let sale = vesting_sale();
+
+        let instant = FlashSale { vesting_cliff: 0, vesting_duration: 0, ..sale.clone() };
+        let mut accounts = claim_accounts(&instant, &bought(100), [4; 32]);
+        assert_eq!(run(&mut accounts), Some(ProgramError::InvalidArgument));
+
+        let missed_soft_cap = FlashSale { soft_cap: 500, ..sale.clone() };
+        let mut accounts = claim_accounts(&missed_soft_cap, &bought(100), [4; 32]);
+        assert_eq!(run(&mut accounts), Some(FlashSaleError::RefundUnavailable.into()));
+
+        let mut other_mint = claim_accounts(&sale, &bought(100), [4; 32]);
+        other_mint[3].key = [6; 32];
+        assert_eq!(run(&mut other_mint), Some(ProgramError::InvalidArgument));
+
+        let mut not_owned = claim_accounts(&sale, &bought(100), [4; 32]);
+        not_owned[2].data = token_account_data(&sale.mint_address, &[8; 32], 0);
+        assert_eq!(run(&mut not_owned), Some(ProgramError::IllegalOwner));
+
+        let mut other_record = claim_accounts(&sale, &bought(100), [4; 32]);
+        other_record[1].key = program_address(&[b"buyer", &SALE, &[8; 32]]);
+        assert_eq!(run(&mut other_record), Some(ProgramError::InvalidSeeds));
+
+        let mut other_deposit = claim_accounts(&sale, &bought(100), [4; 32]);
+        other_deposit[5].data = token_account_data(&sale.mint_address, &[8; 32], 1_000);
+        assert_eq!(run(&mut other_deposit), Some(ProgramError::IllegalOwner));
+
+        let mut forged_sale = claim_accounts(&sale, &bought(100), [4; 32]);
+        forged_sale[6].owner = [8; 32];
+        assert_eq!(run(&mut forged_sale), Some(ProgramError::IllegalOwner));
+    }
+}
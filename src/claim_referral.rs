@@ -0,0 +1,191 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::sysvars::clock::Clock;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, ProgramResult};
+
+use crate::error::FlashSaleError;
+use crate::events::{emit_withdrawal, WithdrawalKind};
+use crate::init_flash_sale::FlashSale;
+use crate::proceeds_vault::{pay_out, PaymentTokenAccounts};
+use crate::referral_record::load_referral_record;
+use crate::utils::check_owner;
+
+pub fn claim_referral(accounts: &[AccountInfo], _instruction_data: &[u8]) -> ProgramResult {
+    claim_referral_at(accounts, Clock::get()?.unix_timestamp)
+}
+
+fn claim_referral_at(accounts: &[AccountInfo], now: i64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let referrer = accounts_iter.next().unwrap();
+    let referral_record_pda = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+    let proceeds_vault_pda = accounts_iter.next().unwrap();
+    let receiver = accounts_iter.next().unwrap();
+
+    if !referrer.is_signer(){
+        msg!("Referrer must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    // Rewards come out of the proceeds, so they unlock with them; on a failed
+    // soft-cap sale they are refunded to the buyers instead.
+    if !flash_sale_settings.proceeds_unlocked(now) {
+        msg!("Referral rewards cannot be claimed yet");
+        return Err(FlashSaleError::WithdrawLocked.into());
+    }
+
+    let mut referral_record = load_referral_record(referrer.key(), referral_record_pda, flash_sale_pda)?;
+    let available = referral_record.earned.saturating_sub(referral_record.claimed);
+    if available == 0 {
+        msg!("Nothing to claim");
+        return Err(FlashSaleError::NothingToClaim.into());
+    }
+
+    referral_record.claimed = referral_record.earned;
+    referral_record.write_to_slice(&mut referral_record_pda.try_borrow_mut_data()?)?;
+
+    flash_sale_settings.referral_claimed = flash_sale_settings
+        .referral_claimed
+        .checked_add(available)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    let payment_accounts = PaymentTokenAccounts::from_iter(&flash_sale_settings, accounts_iter)?;
+    pay_out(
+        flash_sale_pda,
+        proceeds_vault_pda,
+        receiver,
+        payment_accounts.as_ref(),
+        available,
+    )?;
+
+    emit_withdrawal(flash_sale_pda.key(), WithdrawalKind::Referral, receiver.key(), available, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buyer_record::tests::record;
+    use crate::buyer_record::BuyerRecord;
+    use crate::init_flash_sale::tests::{sale, sale_data};
+    use crate::proceeds_vault::ProceedsWithdrawRule;
+    use crate::referral_record::{ReferralRecord, REFERRAL_RECORD_ACCOUNT_SIZE};
+    use crate::utils::tests::{program_address, test_accounts, TestAccount};
+    use pinocchio::pubkey::Pubkey;
+
+    const SALE: Pubkey = [5; 32];
+    const REFERRER: Pubkey = [7; 32];
+
+    // A SOL sale that ended at 2_000, owing REFERRER 20 of the 2_500 raised.
+    fn ended_sale() -> FlashSale {
+        FlashSale {
+            withdraw_rule: ProceedsWithdrawRule::AfterSaleEnd,
+            total_proceeds: 2_500,
+            referral_earned: 20,
+            ..sale("Ticket")
+        }
+    }
+
+    fn referral_data(earned: u64) -> Vec<u8> {
+        let record = ReferralRecord { sale_address: SALE, referrer_address: REFERRER, volume: 2_000, earned, claimed: 0 };
+        let mut data = vec![0; REFERRAL_RECORD_ACCOUNT_SIZE];
+        record.write_to_slice(&mut data).unwrap();
+        data
+    }
+
+    fn claim_referral_accounts(sale: &FlashSale) -> Vec<TestAccount> {
+        let mut accounts = vec![
+            TestAccount::wallet(REFERRER).signer(),
+            TestAccount::new(program_address(&[b"referral", &SALE, &REFERRER]), crate::id(), referral_data(20)),
+            TestAccount::new(SALE, crate::id(), sale_data(sale)),
+            TestAccount::new(program_address(&[b"proceeds", &SALE]), crate::id(), Vec::new()),
+            TestAccount::wallet([4; 32]),
+        ];
+        accounts.extend((0..3).map(|_| TestAccount::wallet(crate::id())));
+        accounts
+    }
+
+    fn run(accounts: &[TestAccount], now: i64) -> Option<ProgramError> {
+        claim_referral_at(&test_accounts(accounts).infos, now).err()
+    }
+
+    #[test]
+    fn referral_rewards_unlock_with_the_proceeds() {
+        let mut sale = FlashSale {
+            withdraw_rule: ProceedsWithdrawRule::Immediate,
+            soft_cap: 1_000,
+            total_proceeds: 500,
+            referral_earned: 20,
+            ..sale("Ticket")
+        };
+        assert!(!sale.proceeds_unlocked(0));
+
+        let record = BuyerRecord {
+            purchased: 1_000,
+            received: 1_000,
+            paid: 480,
+            referral_fees: 20,
+            ..record(SALE, [3; 32])
+        };
+        sale.refund_purchase(&record, 0);
+        assert_eq!(sale.referral_earned, 0);
+
+        sale.total_proceeds = 1_000;
+        assert!(sale.proceeds_unlocked(0));
+    }
+
+    #[test]
+    fn referrers_claim_their_rewards_once() {
+        let accounts = test_accounts(&claim_referral_accounts(&ended_sale()));
+        let accounts = &accounts.infos;
+        claim_referral_at(accounts, 2_500).unwrap();
+
+        assert_eq!(accounts[4].lamports(), 1_000_000_020);
+        assert_eq!(accounts[3].lamports(), 999_999_980);
+        let claimed = FlashSale::try_from(accounts[2].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!(claimed.referral_claimed, 20);
+        let record = ReferralRecord::try_from(accounts[1].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!(record.claimed, 20);
+
+        assert_eq!(claim_referral_at(accounts, 2_600).err(), Some(FlashSaleError::NothingToClaim.into()));
+    }
+
+    #[test]
+    fn referral_claims_check_the_referrer_and_the_unlock() {
+        let sale = ended_sale();
+
+        let mut unsigned = claim_referral_accounts(&sale);
+        unsigned[0] = TestAccount::wallet(REFERRER);
+        assert_eq!(run(&unsigned, 2_500), Some(ProgramError::MissingRequiredSignature));
+
+        let mut other_referrer = claim_referral_accounts(&sale);
+        other_referrer[0] = TestAccount::wallet([8; 32]).signer();
+        assert_eq!(run(&other_referrer, 2_500), Some(ProgramError::InvalidSeeds));
+
+        let mut forged_record = claim_referral_accounts(&sale);
+        forged_record[1].owner = [8; 32];
+        assert_eq!(run(&forged_record, 2_500), Some(ProgramError::IllegalOwner));
+
+        let mut forged_sale = claim_referral_accounts(&sale);
+        forged_sale[2].owner = [8; 32];
+        assert_eq!(run(&forged_sale, 2_500), Some(ProgramError::IllegalOwner));
+
+        let mut other_vault = claim_referral_accounts(&sale);
+        other_vault[3].key = program_address(&[b"proceeds", &[8; 32]]);
+        assert_eq!(run(&other_vault, 2_500), Some(ProgramError::InvalidSeeds));
+
+        assert_eq!(run(&claim_referral_accounts(&sale), 1_999), Some(FlashSaleError::WithdrawLocked.into()));
+        let missed_soft_cap = FlashSale { soft_cap: 5_000, ..sale };
+        assert_eq!(
+            run(&claim_referral_accounts(&missed_soft_cap), 2_500),
+            Some(FlashSaleError::WithdrawLocked.into())
+        );
+    }
+}
@@ -1,45 +1,26 @@
-use pinocchio::cpi::{invoke, invoke_signed};
-use pinocchio::instruction::{AccountMeta, Instruction, Signer};
-use pinocchio::sysvars::rent::Rent;
+use pinocchio::instruction::Signer;
 use pinocchio::sysvars::Sysvar;
-use pinocchio_system::instructions::{Assign, Transfer};
-use shank::{ShankAccount, ShankInstruction, ShankType};
 
 use pinocchio::account_info::AccountInfo;
 use pinocchio::program_error::ProgramError;
-use pinocchio::pubkey::{find_program_address, Pubkey};
 use pinocchio::{msg, seeds, ProgramResult};
 
 use pinocchio::sysvars::clock::Clock;
 
-use solana_program::pubkey::Pubkey as SPK;
-
-use crate::get_token::GetTokenArgs;
+use crate::error::FlashSaleError;
+use crate::events::{FlashSaleEvent, SaleClosed};
 use crate::init_flash_sale::FlashSale;
-use crate::utils::{check_address, check_address_is_any, check_owner};
-
-fn deinit_account_if_exists(account: &AccountInfo, receiver: &AccountInfo, signers: &[Signer]) -> ProgramResult {
-    let lamports = *account.try_borrow_lamports().unwrap();
-    if lamports == 0 {
-        return Ok(());
-    }
-
-    *account.try_borrow_mut_lamports().unwrap() = 0;
-    *receiver.try_borrow_mut_lamports().unwrap() += lamports;
-
-    /*Assign {
-        account: account,
-        owner: &pinocchio_system::id(),
-    }
-    .invoke_signed(signers)?;*/
-
-    Ok(())
-}
-
-pub fn close_sale(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+use crate::proceeds_vault::{close_proceeds_vault, PaymentTokenAccounts};
+use crate::registry::{unregister_sale, GLOBAL_REGISTRY_OWNER, NOT_REGISTERED};
+use crate::utils::{
+    check_address, check_address_is_any, check_owner, close_token_account, deinit_account_if_exists,
+    find_program_address, get_mint_decimals, get_token_account_amount, harvest_withheld_tokens, transfer_tokens,
+};
+
+pub fn close_sale(accounts: &[AccountInfo], _instruction_data: &[u8]) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
     
-    let owner = accounts_iter.next().unwrap();
+    let authority = accounts_iter.next().unwrap();
     let receiver_token_ata = accounts_iter.next().unwrap();
     let token_mint = accounts_iter.next().unwrap();
     let token_deposit_pda = accounts_iter.next().unwrap();
@@ -47,8 +28,10 @@ pub fn close_sale(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramR
     let flash_sale_pda = accounts_iter.next().unwrap();
     let system_program = accounts_iter.next().unwrap();
     let token_program = accounts_iter.next().unwrap();
-    
-    let instruction_args = GetTokenArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+    let _associated_token_program = accounts_iter.next();
+    let _sysvar_clock = accounts_iter.next();
+    let _sysvar_rent = accounts_iter.next();
+    let proceeds_vault_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
 
     let args = {
         let flash_sale_data = flash_sale_pda.try_borrow_data()?;
@@ -56,91 +39,168 @@ pub fn close_sale(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramR
             .map_err(|_| ProgramError::InvalidAccountData)?
     };
 
-    if !owner.is_signer(){
-        msg!("Owner must be signer");
+    if !authority.is_signer(){
+        msg!("Authority must be signer");
         return Err(ProgramError::MissingRequiredSignature);
     }
     if args.mint_address != *token_mint.key(){
         msg!("Unexpected token mint address");
         return Err(ProgramError::InvalidArgument);
     }
-    if args.owner_address != *owner.key(){
-        msg!("Unexpected flash sale owner");
-        return Err(ProgramError::InvalidArgument);
+    if args.authority_address != *authority.key(){
+        msg!("Unexpected flash sale authority");
+        return Err(FlashSaleError::Unauthorized.into());
     }
     check_address(system_program, pinocchio_system::id())?;
     check_address_is_any(token_program, &[pinocchio_token::id(), spl_token_2022::id().to_bytes()])?;
     check_owner(flash_sale_pda, crate::id())?;
 
+    let clock = Clock::get()?;
+    check_closable(&args, clock.unix_timestamp)?;
+
+    let seed = args.seed();
+    let id_seed = args.id_seed();
     let expected_deposit_account = find_program_address(
-        &[b"deposit", args.item_name.as_ref(), token_mint.key(), owner.key()],
+        &[b"deposit", &seed, token_mint.key(), &args.owner_address, &id_seed],
         &crate::id(),
     );
 
     let deposit_binding = [expected_deposit_account.1];
     let deposit_seeds = seeds!(
         b"deposit",
-        args.item_name.as_bytes(),
+        seed.as_slice(),
         token_mint.key(),
-        owner.key(),
+        &args.owner_address,
+        id_seed.as_slice(),
         &deposit_binding
     );
 
-    let expected_sale_account = find_program_address(
-        &[b"sale", args.item_name.as_ref(), token_mint.key(), owner.key()],
-        &crate::id(),
-    );
-
-    let sale_binding = [expected_sale_account.1];
-    let sale_seeds = seeds!(
-        b"sale",
-        args.item_name.as_bytes(),
-        token_mint.key(),
-        owner.key(),
-        &sale_binding
-    );
+    let payment_accounts = PaymentTokenAccounts::from_iter(&args, accounts_iter)?;
+    let registry_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let registry_page_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let global_registry_pda = accounts_iter.next();
+    let global_registry_page_pda = accounts_iter.next();
+    let hook_accounts = accounts_iter.as_slice();
+
+    // Legacy sales were never registered; they pass the program id in place of
+    // the registry accounts, as do sales not listed in the global registry.
+    if args.registry_index != NOT_REGISTERED {
+        unregister_sale(&args.owner_address, registry_pda, registry_page_pda, flash_sale_pda.key(), args.registry_index)?;
+    }
+    if args.global_registry_index != NOT_REGISTERED {
+        unregister_sale(
+            &GLOBAL_REGISTRY_OWNER,
+            global_registry_pda.ok_or(ProgramError::NotEnoughAccountKeys)?,
+            global_registry_page_pda.ok_or(ProgramError::NotEnoughAccountKeys)?,
+            flash_sale_pda.key(),
+            args.global_registry_index,
+        )?;
+    }
 
-    let transfer_tokens_instruction = spl_token_2022::instruction::transfer_checked(
-        &SPK::new_from_array(*token_program.key()),
-        &SPK::new_from_array(*token_deposit_ata.key()),
-        &SPK::new_from_array(*token_mint.key()),
-        &SPK::new_from_array(*receiver_token_ata.key()),
-        &SPK::new_from_array(*token_deposit_pda.key()),
-        &[],
-        instruction_args.amount,
-        instruction_args.decimals,
-    )
-    .unwrap();
-
-    let account_metas: Vec<AccountMeta> = transfer_tokens_instruction
-        .accounts
-        .iter()
-        .map(|m| AccountMeta {
-            is_signer: m.is_signer,
-            is_writable: m.is_writable,
-            pubkey: m.pubkey.as_array(),
-        })
-        .collect();
-
-    let new_ix: Instruction<'_, '_, '_, '_> = Instruction {
-        program_id: &transfer_tokens_instruction.program_id.to_bytes(),
-        data: &transfer_tokens_instruction.data,
-        accounts: &account_metas,
-    };
+    let remaining_amount = get_token_account_amount(token_deposit_ata)?;
 
-    invoke_signed(
-        &new_ix,
-        &[
+    if remaining_amount > 0 {
+        transfer_tokens(
+            token_program,
             token_deposit_ata,
             token_mint,
             receiver_token_ata,
             token_deposit_pda,
-            token_program,
-        ],
-        &[Signer::from(&deposit_seeds)]
+            remaining_amount,
+            get_mint_decimals(token_mint)?,
+            hook_accounts,
+            &[Signer::from(&deposit_seeds)],
+        )?;
+    }
+
+    harvest_withheld_tokens(token_program, token_mint, token_deposit_ata)?;
+    close_token_account(
+        token_program,
+        token_deposit_ata,
+        authority,
+        token_deposit_pda,
+        &[Signer::from(&deposit_seeds)],
     )?;
 
-    deinit_account_if_exists(flash_sale_pda, owner, &[Signer::from(&sale_seeds)])?;
+    close_proceeds_vault(flash_sale_pda, proceeds_vault_pda, authority, payment_accounts.as_ref())?;
+    deinit_account_if_exists(proceeds_vault_pda, authority)?;
+
+    deinit_account_if_exists(flash_sale_pda, authority)?;
+
+    FlashSaleEvent::SaleClosed(SaleClosed {
+        sale: *flash_sale_pda.key(),
+        authority: *authority.key(),
+        remaining_amount,
+        total_sold: args.total_sold,
+        total_proceeds: args.total_proceeds,
+        timestamp: clock.unix_timestamp,
+    })
+    .emit();
+
+    Ok(())
+}
 
+// Closing sweeps the deposit and the vault, so everything owed to buyers,
+// referrers and the protocol has to be settled first.
+fn check_closable(sale: &FlashSale, now: i64) -> ProgramResult {
+    if sale.lock_withdrawals && sale.is_active(now) {
+        msg!("Withdrawals are locked until the sale ends");
+        return Err(FlashSaleError::WithdrawLocked.into());
+    }
+    if sale.withdrawn_proceeds < sale.total_proceeds {
+        msg!("Withdraw the sale proceeds before closing it");
+        return Err(FlashSaleError::ProceedsNotWithdrawn.into());
+    }
+    if sale.held_protocol_fees > 0 {
+        msg!("Protocol fees held for the soft cap must be paid out or refunded before closing the sale");
+        return Err(FlashSaleError::ProceedsNotWithdrawn.into());
+    }
+    if sale.referral_claimed < sale.referral_earned {
+        msg!("Referral rewards must be claimed before closing the sale");
+        return Err(FlashSaleError::ProceedsNotWithdrawn.into());
+    }
+    if sale.vests() && sale.total_claimed < sale.total_sold {
+        msg!("Buyers must claim their vested tokens before closing the sale");
+        return Err(FlashSaleError::TokensUnclaimed.into());
+    }
+    if sale.commit_settled < sale.total_committed {
+        msg!("Every commitment must be claimed before closing the sale");
+        return Err(FlashSaleError::TokensUnclaimed.into());
+    }
     Ok(())
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init_flash_sale::tests::sale;
+
+    #[test]
+    fn locked_sales_only_close_once_they_end() {
+        let locked = FlashSale {
+            lock_withdrawals: true,
+            ..sale("Ticket")
+        };
+        assert_eq!(check_closable(&locked, 1_500), Err(FlashSaleError::WithdrawLocked.into()));
+        assert_eq!(check_closable(&locked, 1_999), Err(FlashSaleError::WithdrawLocked.into()));
+        assert_eq!(check_closable(&locked, 2_000), Ok(()));
+
+        let unlocked = sale("Ticket");
+        assert_eq!(check_closable(&unlocked, 1_500), Ok(()));
+    }
+
+    #[test]
+    fn unsettled_sales_do_not_close() {
+        let unwithdrawn = FlashSale {
+            total_proceeds: 100,
+            withdrawn_proceeds: 40,
+            ..sale("Ticket")
+        };
+        assert_eq!(check_closable(&unwithdrawn, 2_000), Err(FlashSaleError::ProceedsNotWithdrawn.into()));
+
+        let unclaimed_referrals = FlashSale {
+            referral_earned: 10,
+            ..sale("Ticket")
+        };
+        assert_eq!(check_closable(&unclaimed_referrals, 2_000), Err(FlashSaleError::ProceedsNotWithdrawn.into()));
+    }
+}
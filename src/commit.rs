@@ -0,0 +1,314 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::sysvars::clock::Clock;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, ProgramResult};
+use shank::ShankType;
+
+use crate::buyer_record::{load_or_create_buyer_record, BuyerRecord};
+use crate::error::FlashSaleError;
+use crate::events::{emit_withdrawal, Commitment, FlashSaleEvent, Purchase, WithdrawalKind};
+use crate::init_config::load_config;
+use crate::init_flash_sale::FlashSale;
+use crate::proceeds_vault::{collect_payment, pay_out, PaymentTokenAccounts};
+use crate::utils::{check_address, check_owner, check_token_account_owner};
+
+#[derive(Debug, Clone, ShankType)]
+pub struct CommitArgs {
+    pub amount: u64,
+}
+
+impl TryFrom<&[u8]> for CommitArgs {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 8 {
+            return Err("Unexpected end of data".to_string());
+        }
+        let amount = u64::from_le_bytes(data[0..8].try_into().unwrap());
+
+        Ok(CommitArgs {
+            amount
+        })
+    }
+}
+
+// Commit sales: buyers deposit payment while the sale runs, and the tokens are
+// split pro rata once it ends (see `settle_commitment`).
+pub fn commit(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    commit_at(accounts, instruction_data, Clock::get()?.unix_timestamp)
+}
+
+fn commit_at(accounts: &[AccountInfo], instruction_data: &[u8], now: i64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let payer = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+    let proceeds_vault_pda = accounts_iter.next().unwrap();
+    let buyer_record_pda = accounts_iter.next().unwrap();
+    let system_program = accounts_iter.next().unwrap();
+    let payer_payment_account = accounts_iter.next();
+
+    let args = CommitArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    if !payer.is_signer(){
+        msg!("Payer must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_address(system_program, pinocchio_system::id())?;
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if !flash_sale_settings.commit_mode {
+        msg!("Sale does not take commitments");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if flash_sale_settings.paused {
+        msg!("Sale is paused");
+        return Err(FlashSaleError::SalePaused.into());
+    }
+    if !flash_sale_settings.is_active(now) {
+        msg!("Sale has ended");
+        return Err(FlashSaleError::SaleEnded.into());
+    }
+    if args.amount == 0 {
+        msg!("Commitment must not be empty");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let payment_accounts = PaymentTokenAccounts::from_iter(&flash_sale_settings, accounts_iter)?;
+    let config_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if load_config(config_pda)?.is_some_and(|config| config.paused) {
+        msg!("Protocol is paused");
+        return Err(FlashSaleError::ProtocolPaused.into());
+    }
+
+    let mut buyer_record = load_or_create_buyer_record(payer, payer.key(), buyer_record_pda, flash_sale_pda)?;
+    buyer_record.paid = buyer_record
+        .paid
+        .checked_add(args.amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    // The per-wallet limit caps what a commitment could buy at full allocation.
+    if flash_sale_settings.max_per_wallet > 0
+        && buyer_record.paid > flash_sale_settings.cost_for(flash_sale_settings.max_per_wallet)?
+    {
+        msg!("Commitment exceeds the per-wallet limit");
+        return Err(ProgramError::InvalidArgument);
+    }
+    buyer_record.write_to_slice(&mut buyer_record_pda.try_borrow_mut_data()?)?;
+
+    flash_sale_settings.total_committed = flash_sale_settings
+        .total_committed
+        .checked_add(args.amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    collect_payment(
+        payer,
+        payer_payment_account,
+        flash_sale_pda,
+        proceeds_vault_pda,
+        payment_accounts.as_ref(),
+        args.amount,
+    )?;
+
+    FlashSaleEvent::Commitment(Commitment {
+        sale: *flash_sale_pda.key(),
+        buyer: *payer.key(),
+        amount: args.amount,
+        committed: buyer_record.paid,
+        total_committed: flash_sale_settings.total_committed,
+        timestamp: now,
+    })
+    .emit();
+    Ok(())
+}
+
+pub struct SettlementAccounts<'a> {
+    pub proceeds_vault_pda: &'a AccountInfo,
+    pub config_pda: &'a AccountInfo,
+    pub fee_recipient: &'a AccountInfo,
+    pub buyer_payment_account: Option<&'a AccountInfo>,
+    pub payment_accounts: Option<PaymentTokenAccounts<'a>>,
+}
+
+// Turns a buyer's commitment into a purchase once the sale has ended: the
+// buyer is allocated min(requested, pro rata share) and the payment beyond the
+// allocation's cost is refunded. Returns false when nothing was allocated, in
+// which case the whole commitment has been refunded.
+pub fn settle_commitment(
+    buyer: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+    flash_sale_settings: &mut FlashSale,
+    buyer_record: &mut BuyerRecord,
+    accounts: &SettlementAccounts,
+    now: i64,
+) -> Result<bool, ProgramError> {
+    if flash_sale_settings.is_active(now) {
+        msg!("Commitments are settled after the sale ends");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let committed = buyer_record.paid;
+    let allocation = flash_sale_settings.commit_allocation(committed);
+    let cost = flash_sale_settings.cost_for(allocation)?;
+    let config = load_config(accounts.config_pda)?;
+    let fee = config.as_ref().map_or(0, |config| config.fee_for(cost));
+    let excess = committed - cost;
+
+    flash_sale_settings.commit_settled = flash_sale_settings
+        .commit_settled
+        .checked_add(committed)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.total_sold = flash_sale_settings
+        .total_sold
+        .checked_add(allocation)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.total_proceeds = flash_sale_settings
+        .total_proceeds
+        .checked_add(cost - fee)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    buyer_record.purchased = allocation;
+    buyer_record.paid = cost - fee;
+
+    let payment_accounts = accounts.payment_accounts.as_ref();
+    if let (Some(config), true) = (config.as_ref(), fee > 0) {
+        match payment_accounts {
+            Some(_) => check_token_account_owner(accounts.fee_recipient, &config.fee_recipient)?,
+            None => check_address(accounts.fee_recipient, config.fee_recipient)?,
+        }
+        pay_out(flash_sale_pda, accounts.proceeds_vault_pda, accounts.fee_recipient, payment_accounts, fee)?;
+    }
+    if excess > 0 {
+        let refund_receiver = match payment_accounts {
+            Some(_) => {
+                let buyer_payment_account = accounts.buyer_payment_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+                check_token_account_owner(buyer_payment_account, buyer.key())?;
+                buyer_payment_account
+            }
+            None => buyer,
+        };
+        pay_out(flash_sale_pda, accounts.proceeds_vault_pda, refund_receiver, payment_accounts, excess)?;
+        emit_withdrawal(flash_sale_pda.key(), WithdrawalKind::Refund, refund_receiver.key(), excess, now)?;
+    }
+
+    FlashSaleEvent::Purchase(Purchase {
+        sale: *flash_sale_pda.key(),
+        payer: *buyer.key(),
+        recipient: *buyer.key(),
+        referrer: [0; 32],
+        amount: allocation,
+        net_amount: allocation,
+        cost,
+        protocol_fee: fee,
+        referral_fee: 0,
+        total_sold: flash_sale_settings.total_sold,
+        timestamp: now,
+    })
+    .emit();
+
+    Ok(allocation > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buyer_record::tests::{record, record_data};
+    use crate::init_flash_sale::tests::{sale, sale_data};
+    use crate::utils::tests::{program_address, test_accounts, TestAccount};
+    use pinocchio::pubkey::Pubkey;
+
+    const SALE: Pubkey = [5; 32];
+    const BUYER: Pubkey = [7; 32];
+
+    // A SOL commit sale running until 2_000 with 40_000 committed, 10_000 of
+    // it by BUYER.
+    fn commit_sale() -> FlashSale {
+        FlashSale { commit_mode: true, total_sold: 0, total_committed: 40_000, ..sale("Ticket") }
+    }
+
+    fn commit_accounts(sale: &FlashSale) -> Vec<TestAccount> {
+        let record = BuyerRecord { paid: 10_000, ..record(SALE, BUYER) };
+        let mut accounts = vec![
+            TestAccount::wallet(BUYER).signer(),
+            TestAccount::new(SALE, crate::id(), sale_data(sale)),
+            TestAccount::new(program_address(&[b"proceeds", &SALE]), crate::id(), Vec::new()),
+            TestAccount::new(program_address(&[b"buyer", &SALE, &BUYER]), crate::id(), record_data(&record)),
+            TestAccount::wallet(pinocchio_system::id()),
+        ];
+        // No payer token account or payment token accounts for a SOL sale.
+        accounts.extend((0..4).map(|_| TestAccount::wallet(crate::id())));
+        accounts.push(TestAccount::wallet(program_address(&[b"config"])));
+        accounts
+    }
+
+    fn run(accounts: &[TestAccount], amount: u64, now: i64) -> ProgramResult {
+        commit_at(&test_accounts(accounts).infos, &amount.to_le_bytes(), now)
+    }
+
+    #[test]
+    fn commitments_add_up_while_the_sale_runs() {
+        let accounts = test_accounts(&commit_accounts(&commit_sale()));
+        let accounts = &accounts.infos;
+        commit_at(accounts, &5_000u64.to_le_bytes(), 1_500).unwrap();
+
+        let committed = FlashSale::try_from(accounts[1].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!(committed.total_committed, 45_000);
+        let record = BuyerRecord::try_from(accounts[3].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!(record.paid, 15_000);
+    }
+
+    #[test]
+    fn commitments_are_checked_before_payment() {
+        let accounts = commit_accounts(&commit_sale());
+        assert_eq!(run(&accounts, 0, 1_500).err(), Some(ProgramError::InvalidArgument));
+        assert_eq!(run(&accounts, 5_000, 2_000).err(), Some(FlashSaleError::SaleEnded.into()));
+
+        let mut unsigned = commit_accounts(&commit_sale());
+        unsigned[0] = TestAccount::wallet(BUYER);
+        assert_eq!(run(&unsigned, 5_000, 1_500).err(), Some(ProgramError::MissingRequiredSignature));
+
+        let paused = FlashSale { paused: true, ..commit_sale() };
+        assert_eq!(run(&commit_accounts(&paused), 5_000, 1_500).err(), Some(FlashSaleError::SalePaused.into()));
+
+        let fixed_price = FlashSale { commit_mode: false, ..commit_sale() };
+        assert_eq!(run(&commit_accounts(&fixed_price), 5_000, 1_500).err(), Some(ProgramError::InvalidArgument));
+
+        // 1_500 tokens cost 15_000, and BUYER already committed 10_000.
+        let capped = FlashSale { max_per_wallet: 1_500, ..commit_sale() };
+        assert_eq!(run(&commit_accounts(&capped), 5_001, 1_500).err(), Some(ProgramError::InvalidArgument));
+        run(&commit_accounts(&capped), 5_000, 1_500).unwrap();
+
+        let mut wrong_record = commit_accounts(&commit_sale());
+        wrong_record[3].key = program_address(&[b"buyer", &SALE, &[8; 32]]);
+        assert_eq!(run(&wrong_record, 5_000, 1_500).err(), Some(ProgramError::InvalidSeeds));
+    }
+
+    #[test]
+    fn commitments_are_allocated_pro_rata_when_oversubscribed() {
+        // 1_000 tokens at 10 per token: 10_000 fills the sale exactly.
+        let undersubscribed = FlashSale {
+            total_committed: 5_000,
+            ..commit_sale()
+        };
+        assert_eq!(undersubscribed.commit_allocation(2_000), 200);
+        assert_eq!(undersubscribed.reserved_supply(), 500);
+
+        let oversubscribed = commit_sale();
+        assert_eq!(oversubscribed.commit_allocation(10_000), 250);
+        assert_eq!(oversubscribed.commit_allocation(1), 0);
+        assert_eq!(oversubscribed.reserved_supply(), 1_000);
+
+        assert_eq!(FlashSale { total_committed: 0, ..oversubscribed }.commit_allocation(10_000), 0);
+    }
+
+    #[test]
+    fn commit_sale_supply_is_fixed_once_the_sale_ends() {
+        assert!(!commit_sale().supply_frozen(1_999));
+        assert!(commit_sale().supply_frozen(2_000));
+        assert!(!FlashSale { commit_mode: false, ..commit_sale() }.supply_frozen(2_000));
+    }
+}
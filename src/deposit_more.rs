@@ -0,0 +1,77 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::sysvars::clock::Clock;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, ProgramResult};
+
+use crate::error::FlashSaleError;
+use crate::events::emit_sale_updated;
+use crate::get_token::GetTokenArgs;
+use crate::init_flash_sale::FlashSale;
+use crate::utils::{check_address_is_any, check_owner, check_token_account_owner, find_program_address, get_mint_decimals, get_transfer_fee, transfer_tokens};
+
+pub fn deposit_more(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let authority = accounts_iter.next().unwrap();
+    let token_mint = accounts_iter.next().unwrap();
+    let source_token_account = accounts_iter.next().unwrap();
+    let token_deposit_ata = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+    let token_program = accounts_iter.next().unwrap();
+    let hook_accounts = accounts_iter.as_slice();
+
+    let args = GetTokenArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    if !authority.is_signer(){
+        msg!("Authority must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_address_is_any(token_program, &[pinocchio_token::id(), spl_token_2022::id().to_bytes()])?;
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.mint_address != *token_mint.key(){
+        msg!("Unexpected token mint address");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if flash_sale_settings.authority_address != *authority.key(){
+        msg!("Unexpected flash sale authority");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+    if flash_sale_settings.supply_frozen(Clock::get()?.unix_timestamp) {
+        msg!("Commit sale supply is fixed once the sale ends");
+        return Err(FlashSaleError::SaleEnded.into());
+    }
+
+    let seed = flash_sale_settings.seed();
+    let id_seed = flash_sale_settings.id_seed();
+    let expected_deposit_account = find_program_address(
+        &[b"deposit", &seed, token_mint.key(), &flash_sale_settings.owner_address, &id_seed],
+        &crate::id(),
+    );
+    check_token_account_owner(token_deposit_ata, &expected_deposit_account.0)?;
+
+    flash_sale_settings.total_supply = flash_sale_settings
+        .total_supply
+        .checked_add(args.amount - get_transfer_fee(token_mint, args.amount)?.unwrap_or(0))
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    transfer_tokens(
+        token_program,
+        source_token_account,
+        token_mint,
+        token_deposit_ata,
+        authority,
+        args.amount,
+        get_mint_decimals(token_mint)?,
+        hook_accounts,
+        &[],
+    )?;
+
+    emit_sale_updated(flash_sale_pda.key(), &flash_sale_settings)
+}
@@ -0,0 +1,26 @@
+use pinocchio::program_error::ProgramError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum FlashSaleError {
+    SalePaused = 0,
+    Unauthorized = 1,
+    WithdrawLocked = 2,
+    ProceedsNotWithdrawn = 3,
+    SaleEnded = 4,
+    RefundUnavailable = 5,
+    InvalidFee = 6,
+    ProtocolPaused = 7,
+    InvalidPayees = 8,
+    NothingToClaim = 9,
+    PurchaseExpired = 10,
+    SlippageExceeded = 11,
+    TokensUnclaimed = 12,
+    InsufficientSupply = 13,
+}
+
+impl From<FlashSaleError> for ProgramError {
+    fn from(e: FlashSaleError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
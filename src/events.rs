@@ -0,0 +1,385 @@
+use pinocchio::log::sol_log_data;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::sysvars::{clock::Clock, Sysvar};
+use pinocchio::ProgramResult;
+
+use crate::init_flash_sale::FlashSale;
+use crate::utils::{read_i64, read_pubkey, read_string, read_u64, read_u8};
+
+// Events are written with `sol_log_data` and show up in the transaction logs
+// as `Program data: <base64>`. The first byte of the payload is the event tag,
+// followed by the fields in declaration order, little-endian.
+pub const SALE_CREATED_TAG: u8 = 0;
+pub const PURCHASE_TAG: u8 = 1;
+pub const SALE_UPDATED_TAG: u8 = 2;
+pub const SALE_CLOSED_TAG: u8 = 3;
+pub const WITHDRAWAL_TAG: u8 = 4;
+pub const COMMITMENT_TAG: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaleCreated {
+    pub sale: Pubkey,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub item_name: String,
+    pub price: u64,
+    pub price_denominator: u64,
+    pub total_supply: u64,
+    pub end_timestamp: i64,
+    pub payment_mint: Pubkey,
+    pub soft_cap: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Purchase {
+    pub sale: Pubkey,
+    pub payer: Pubkey,
+    pub recipient: Pubkey,
+    pub referrer: Pubkey,
+    pub amount: u64,
+    pub net_amount: u64,
+    pub cost: u64,
+    pub protocol_fee: u64,
+    pub referral_fee: u64,
+    pub total_sold: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaleUpdated {
+    pub sale: Pubkey,
+    pub price: u64,
+    pub price_denominator: u64,
+    pub end_timestamp: i64,
+    pub total_supply: u64,
+    pub max_per_wallet: u64,
+    pub paused: bool,
+    pub operator: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaleClosed {
+    pub sale: Pubkey,
+    pub authority: Pubkey,
+    pub remaining_amount: u64,
+    pub total_sold: u64,
+    pub total_proceeds: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WithdrawalKind {
+    Proceeds,
+    Referral,
+    Refund,
+    UnsoldTokens,
+    VestedTokens,
+    ProtocolFees,
+}
+
+impl TryFrom<u8> for WithdrawalKind {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(WithdrawalKind::Proceeds),
+            1 => Ok(WithdrawalKind::Referral),
+            2 => Ok(WithdrawalKind::Refund),
+            3 => Ok(WithdrawalKind::UnsoldTokens),
+            4 => Ok(WithdrawalKind::VestedTokens),
+            5 => Ok(WithdrawalKind::ProtocolFees),
+            _ => Err("Invalid withdrawal kind".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Withdrawal {
+    pub sale: Pubkey,
+    pub kind: WithdrawalKind,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+// A commit sale took a commitment. It becomes a Purchase when settled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commitment {
+    pub sale: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub committed: u64,
+    pub total_committed: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlashSaleEvent {
+    SaleCreated(SaleCreated),
+    Purchase(Purchase),
+    SaleUpdated(SaleUpdated),
+    SaleClosed(SaleClosed),
+    Withdrawal(Withdrawal),
+    Commitment(Commitment),
+}
+
+impl FlashSaleEvent {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            FlashSaleEvent::SaleCreated(event) => {
+                buf.push(SALE_CREATED_TAG);
+                buf.extend_from_slice(&event.sale);
+                buf.extend_from_slice(&event.mint);
+                buf.extend_from_slice(&event.owner);
+                buf.extend_from_slice(&(event.item_name.len() as u32).to_le_bytes());
+                buf.extend_from_slice(event.item_name.as_bytes());
+                buf.extend_from_slice(&event.price.to_le_bytes());
+                buf.extend_from_slice(&event.price_denominator.to_le_bytes());
+                buf.extend_from_slice(&event.total_supply.to_le_bytes());
+                buf.extend_from_slice(&event.end_timestamp.to_le_bytes());
+                buf.extend_from_slice(&event.payment_mint);
+                buf.extend_from_slice(&event.soft_cap.to_le_bytes());
+                buf.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+            FlashSaleEvent::Purchase(event) => {
+                buf.push(PURCHASE_TAG);
+                buf.extend_from_slice(&event.sale);
+                buf.extend_from_slice(&event.payer);
+                buf.extend_from_slice(&event.recipient);
+                buf.extend_from_slice(&event.referrer);
+                buf.extend_from_slice(&event.amount.to_le_bytes());
+                buf.extend_from_slice(&event.net_amount.to_le_bytes());
+                buf.extend_from_slice(&event.cost.to_le_bytes());
+                buf.extend_from_slice(&event.protocol_fee.to_le_bytes());
+                buf.extend_from_slice(&event.referral_fee.to_le_bytes());
+                buf.extend_from_slice(&event.total_sold.to_le_bytes());
+                buf.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+            FlashSaleEvent::SaleUpdated(event) => {
+                buf.push(SALE_UPDATED_TAG);
+                buf.extend_from_slice(&event.sale);
+                buf.extend_from_slice(&event.price.to_le_bytes());
+                buf.extend_from_slice(&event.price_denominator.to_le_bytes());
+                buf.extend_from_slice(&event.end_timestamp.to_le_bytes());
+                buf.extend_from_slice(&event.total_supply.to_le_bytes());
+                buf.extend_from_slice(&event.max_per_wallet.to_le_bytes());
+                buf.push(event.paused as u8);
+                buf.extend_from_slice(&event.operator);
+                buf.extend_from_slice(&event.authority);
+                buf.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+            FlashSaleEvent::SaleClosed(event) => {
+                buf.push(SALE_CLOSED_TAG);
+                buf.extend_from_slice(&event.sale);
+                buf.extend_from_slice(&event.authority);
+                buf.extend_from_slice(&event.remaining_amount.to_le_bytes());
+                buf.extend_from_slice(&event.total_sold.to_le_bytes());
+                buf.extend_from_slice(&event.total_proceeds.to_le_bytes());
+                buf.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+            FlashSaleEvent::Withdrawal(event) => {
+                buf.push(WITHDRAWAL_TAG);
+                buf.extend_from_slice(&event.sale);
+                buf.push(event.kind as u8);
+                buf.extend_from_slice(&event.receiver);
+                buf.extend_from_slice(&event.amount.to_le_bytes());
+                buf.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+            FlashSaleEvent::Commitment(event) => {
+                buf.push(COMMITMENT_TAG);
+                buf.extend_from_slice(&event.sale);
+                buf.extend_from_slice(&event.buyer);
+                buf.extend_from_slice(&event.amount.to_le_bytes());
+                buf.extend_from_slice(&event.committed.to_le_bytes());
+                buf.extend_from_slice(&event.total_committed.to_le_bytes());
+                buf.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+        }
+        buf
+    }
+
+    pub fn emit(&self) {
+        sol_log_data(&[&self.to_bytes()]);
+    }
+}
+
+// Decodes the payload of a `Program data:` log line once it has been base64
+// decoded.
+impl TryFrom<&[u8]> for FlashSaleEvent {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let mut offset = 0;
+        let tag = read_u8(data, &mut offset)?;
+        let event = match tag {
+            SALE_CREATED_TAG => FlashSaleEvent::SaleCreated(SaleCreated {
+                sale: read_pubkey(data, &mut offset)?,
+                mint: read_pubkey(data, &mut offset)?,
+                owner: read_pubkey(data, &mut offset)?,
+                item_name: read_string(data, &mut offset)?,
+                price: read_u64(data, &mut offset)?,
+                price_denominator: read_u64(data, &mut offset)?,
+                total_supply: read_u64(data, &mut offset)?,
+                end_timestamp: read_i64(data, &mut offset)?,
+                payment_mint: read_pubkey(data, &mut offset)?,
+                soft_cap: read_u64(data, &mut offset)?,
+                timestamp: read_i64(data, &mut offset)?,
+            }),
+            PURCHASE_TAG => FlashSaleEvent::Purchase(Purchase {
+                sale: read_pubkey(data, &mut offset)?,
+                payer: read_pubkey(data, &mut offset)?,
+                recipient: read_pubkey(data, &mut offset)?,
+                referrer: read_pubkey(data, &mut offset)?,
+                amount: read_u64(data, &mut offset)?,
+                net_amount: read_u64(data, &mut offset)?,
+                cost: read_u64(data, &mut offset)?,
+                protocol_fee: read_u64(data, &mut offset)?,
+                referral_fee: read_u64(data, &mut offset)?,
+                total_sold: read_u64(data, &mut offset)?,
+                timestamp: read_i64(data, &mut offset)?,
+            }),
+            SALE_UPDATED_TAG => FlashSaleEvent::SaleUpdated(SaleUpdated {
+                sale: read_pubkey(data, &mut offset)?,
+                price: read_u64(data, &mut offset)?,
+                price_denominator: read_u64(data, &mut offset)?,
+                end_timestamp: read_i64(data, &mut offset)?,
+                total_supply: read_u64(data, &mut offset)?,
+                max_per_wallet: read_u64(data, &mut offset)?,
+                paused: read_u8(data, &mut offset)? != 0,
+                operator: read_pubkey(data, &mut offset)?,
+                authority: read_pubkey(data, &mut offset)?,
+                timestamp: read_i64(data, &mut offset)?,
+            }),
+            SALE_CLOSED_TAG => FlashSaleEvent::SaleClosed(SaleClosed {
+                sale: read_pubkey(data, &mut offset)?,
+                authority: read_pubkey(data, &mut offset)?,
+                remaining_amount: read_u64(data, &mut offset)?,
+                total_sold: read_u64(data, &mut offset)?,
+                total_proceeds: read_u64(data, &mut offset)?,
+                timestamp: read_i64(data, &mut offset)?,
+            }),
+            WITHDRAWAL_TAG => FlashSaleEvent::Withdrawal(Withdrawal {
+                sale: read_pubkey(data, &mut offset)?,
+                kind: WithdrawalKind::try_from(read_u8(data, &mut offset)?)?,
+                receiver: read_pubkey(data, &mut offset)?,
+                amount: read_u64(data, &mut offset)?,
+                timestamp: read_i64(data, &mut offset)?,
+            }),
+            COMMITMENT_TAG => FlashSaleEvent::Commitment(Commitment {
+                sale: read_pubkey(data, &mut offset)?,
+                buyer: read_pubkey(data, &mut offset)?,
+                amount: read_u64(data, &mut offset)?,
+                committed: read_u64(data, &mut offset)?,
+                total_committed: read_u64(data, &mut offset)?,
+                timestamp: read_i64(data, &mut offset)?,
+            }),
+            _ => return Err("Unknown event tag".to_string()),
+        };
+
+        if offset != data.len() {
+            return Err("Trailing bytes after event".to_string());
+        }
+        Ok(event)
+    }
+}
+
+impl SaleUpdated {
+    pub fn from_sale(sale: &Pubkey, flash_sale_settings: &FlashSale, timestamp: i64) -> Self {
+        SaleUpdated {
+            sale: *sale,
+            price: flash_sale_settings.price,
+            price_denominator: flash_sale_settings.price_denominator,
+            end_timestamp: flash_sale_settings.end_timestamp,
+            total_supply: flash_sale_settings.total_supply,
+            max_per_wallet: flash_sale_settings.max_per_wallet,
+            paused: flash_sale_settings.paused,
+            operator: flash_sale_settings.operator_address,
+            authority: flash_sale_settings.authority_address,
+            timestamp,
+        }
+    }
+}
+
+pub fn emit_sale_updated(sale: &Pubkey, flash_sale_settings: &FlashSale) -> ProgramResult {
+    let clock = Clock::get()?;
+    FlashSaleEvent::SaleUpdated(SaleUpdated::from_sale(sale, flash_sale_settings, clock.unix_timestamp)).emit();
+    Ok(())
+}
+
+pub fn emit_withdrawal(sale: &Pubkey, kind: WithdrawalKind, receiver: &Pubkey, amount: u64, timestamp: i64) -> ProgramResult {
+    FlashSaleEvent::Withdrawal(Withdrawal {
+        sale: *sale,
+        kind,
+        receiver: *receiver,
+        amount,
+        timestamp,
+    })
+    .emit();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sale_created_round_trips_a_multi_byte_name() {
+        let event = FlashSaleEvent::SaleCreated(SaleCreated {
+            sale: [1; 32],
+            mint: [2; 32],
+            owner: [3; 32],
+            item_name: "Édition limitée 🎟".to_string(),
+            price: 5,
+            price_denominator: 1,
+            total_supply: 100,
+            end_timestamp: 1_700_000_000,
+            payment_mint: [0; 32],
+            soft_cap: 0,
+            timestamp: 1_600_000_000,
+        });
+
+        assert_eq!(FlashSaleEvent::try_from(event.to_bytes().as_slice()), Ok(event));
+    }
+
+    #[test]
+    fn commitment_round_trips() {
+        let event = FlashSaleEvent::Commitment(Commitment {
+            sale: [1; 32],
+            buyer: [2; 32],
+            amount: 500,
+            committed: 800,
+            total_committed: 5_000,
+            timestamp: 1_600_000_000,
+        });
+
+        let bytes = event.to_bytes();
+        assert_eq!(bytes[0], COMMITMENT_TAG);
+        assert_eq!(FlashSaleEvent::try_from(bytes.as_slice()), Ok(event));
+    }
+
+    #[test]
+    fn sale_created_rejects_a_name_that_is_not_utf8() {
+        let mut bytes = FlashSaleEvent::SaleCreated(SaleCreated {
+            sale: [1; 32],
+            mint: [2; 32],
+            owner: [3; 32],
+            item_name: "ab".to_string(),
+            price: 5,
+            price_denominator: 1,
+            total_supply: 100,
+            end_timestamp: 0,
+            payment_mint: [0; 32],
+            soft_cap: 0,
+            timestamp: 0,
+        })
+        .to_bytes();
+        // The name starts after the tag, three keys and its length.
+        bytes[1 + 96 + 4] = 0xff;
+
+        assert!(FlashSaleEvent::try_from(bytes.as_slice()).is_err());
+    }
+}
@@ -1,33 +1,107 @@
 use pinocchio::{
-    account_info::AccountInfo, cpi::invoke_signed, instruction::{AccountMeta, Instruction, Signer}, msg, program_error::ProgramError, pubkey::find_program_address, seeds, ProgramResult
+    account_info::AccountInfo, cpi::set_return_data, instruction::Signer, msg, program_error::ProgramError, seeds, ProgramResult
 };
-use shank::{ShankInstruction, ShankType};
+use pinocchio::sysvars::{clock::Clock, Sysvar};
+use shank::ShankType;
 
-use crate::{init_flash_sale::{FlashSale, InitFlashSaleArgs}, utils::{check_address, check_address_is_any, check_owner}};
-use solana_program::pubkey::Pubkey as SPK;
+use crate::{buyer_record::load_or_create_buyer_record, error::FlashSaleError, events::{FlashSaleEvent, Purchase}, init_flash_sale::{FlashSale, PurchaseCheck}, init_config::load_config, proceeds_vault::{collect_fee, collect_payment, PaymentTokenAccounts}, referral_record::record_referral, utils::{check_address, check_address_is_any, check_owner, check_token_account_owner, find_program_address, get_mint_decimals, get_transfer_fee, transfer_tokens}};
 
 #[derive(Debug, Clone, ShankType)]
 pub struct GetTokenArgs {
     pub amount: u64,
-    pub decimals: u8,
 }
 
 impl TryFrom<&[u8]> for GetTokenArgs {
     type Error = String;
 
     fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 8 {
+            return Err("Unexpected end of data".to_string());
+        }
         let amount = u64::from_le_bytes(data[0..8].try_into().unwrap());
-        let decimals = u8::from_le_bytes(data[8..9].try_into().unwrap());
+        // Deprecated layout: older clients append a decimals byte, which is
+        // ignored now that decimals are read from the mint.
+        if data.len() > 8 {
+            msg!("Deprecated instruction format: decimals are read from the mint");
+        }
 
         Ok(GetTokenArgs {
+            amount
+        })
+    }
+}
+
+#[derive(Debug, Clone, ShankType)]
+pub struct GetTokenWithLimitsArgs {
+    pub amount: u64,
+    pub max_total_cost: u64,
+    pub expires_at: i64,
+}
+
+impl TryFrom<&[u8]> for GetTokenWithLimitsArgs {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 24 {
+            return Err("Unexpected end of data".to_string());
+        }
+        let amount = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let max_total_cost = u64::from_le_bytes(data[8..16].try_into().unwrap());
+        let expires_at = i64::from_le_bytes(data[16..24].try_into().unwrap());
+
+        Ok(GetTokenWithLimitsArgs {
             amount,
-            decimals
+            max_total_cost,
+            expires_at,
         })
     }
 }
 
+#[derive(Debug, Clone, ShankType)]
+pub struct PurchaseReceipt {
+    pub amount: u64,
+    pub net_amount: u64,
+    pub transfer_fee: u64,
+    pub cost: u64,
+    pub protocol_fee: u64,
+    pub referral_fee: u64,
+}
+
+pub const PURCHASE_RECEIPT_SIZE: usize = 8 * 6;
+
+impl PurchaseReceipt {
+    pub fn write_to_slice(&self, buf: &mut [u8]) -> Result<(), ProgramError> {
+        buf[0..8].copy_from_slice(&self.amount.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.net_amount.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.transfer_fee.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.cost.to_le_bytes());
+        buf[32..40].copy_from_slice(&self.protocol_fee.to_le_bytes());
+        buf[40..48].copy_from_slice(&self.referral_fee.to_le_bytes());
+
+        Ok(())
+    }
+}
 
 pub fn get_token(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let args = GetTokenArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    purchase(
+        accounts,
+        GetTokenWithLimitsArgs {
+            amount: args.amount,
+            max_total_cost: u64::MAX,
+            expires_at: i64::MAX,
+        },
+    )
+}
+
+pub fn get_token_with_limits(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let args = GetTokenWithLimitsArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    purchase(accounts, args)
+}
+
+fn purchase(accounts: &[AccountInfo], args: GetTokenWithLimitsArgs) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
     let payer = accounts_iter.next().unwrap();
@@ -35,88 +109,252 @@ pub fn get_token(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramRe
     let token_mint = accounts_iter.next().unwrap();
     let token_deposit_pda = accounts_iter.next().unwrap();
     let token_deposit_ata = accounts_iter.next().unwrap();
-    let flash_sale_owner = accounts_iter.next().unwrap();
+    let proceeds_vault_pda = accounts_iter.next().unwrap();
     let flash_sale_pda = accounts_iter.next().unwrap();
     let system_program = accounts_iter.next().unwrap();
     let token_program = accounts_iter.next().unwrap();
+    let associated_token_program = accounts_iter.next().unwrap();
+    let _sysvar_clock = accounts_iter.next();
+    let _sysvar_rent = accounts_iter.next();
+    let buyer_record_pda = accounts_iter.next();
+    let payer_payment_account = accounts_iter.next();
 
-    let flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
-    let flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
         .map_err(|_| ProgramError::InvalidInstructionData)?;
 
-    let args = GetTokenArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
-
-    pinocchio_system::instructions::Transfer {
-        from: payer,
-        to: flash_sale_owner,
-        lamports: flash_sale_settings.price.checked_mul(args.amount).unwrap(),
-    }
-    .invoke()?;
-
     if flash_sale_settings.mint_address != *token_mint.key(){
         msg!("Unexpected token mint address");
         return Err(ProgramError::InvalidArgument);
     }
-    if flash_sale_settings.owner_address != *flash_sale_owner.key(){
-        msg!("Unexpected flash sale owner");
-        return Err(ProgramError::InvalidArgument);
-    }
     check_address(system_program, pinocchio_system::id())?;
+    check_address(associated_token_program, pinocchio_associated_token_account::id())?;
     check_address_is_any(token_program, &[pinocchio_token::id(), spl_token_2022::id().to_bytes()])?;
     check_owner(flash_sale_pda, crate::id())?;
 
-    let stake_ix = spl_token_2022::instruction::transfer_checked(
-        &SPK::new_from_array(*token_program.key()),
-        &SPK::new_from_array(*token_deposit_ata.key()),
-        &SPK::new_from_array(*token_mint.key()),
-        &SPK::new_from_array(*receiver_token_ata.key()),
-        &SPK::new_from_array(*token_deposit_pda.key()),
-        &[],
-        args.amount,
-        args.decimals,
-    )
-    .unwrap();
-
-    let account_metas: Vec<AccountMeta> = stake_ix
-        .accounts
-        .iter()
-        .map(|m| AccountMeta {
-            is_signer: m.is_signer,
-            is_writable: m.is_writable,
-            pubkey: m.pubkey.as_array(),
-        })
-        .collect();
+    let payment_accounts = PaymentTokenAccounts::from_iter(&flash_sale_settings, accounts_iter)?;
+    let config_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let fee_recipient = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    // Clients that only need the trailing transfer-hook accounts pass the
+    // program id in place of the referrer and its record.
+    let referrer = accounts_iter.next().filter(|account| *account.key() != crate::id());
+    let referral_record_pda = accounts_iter.next();
+    // The wallet receiving the tokens; the payer buys for itself when it is
+    // missing or the program id is passed in its place.
+    let recipient = accounts_iter
+        .next()
+        .filter(|account| *account.key() != crate::id())
+        .unwrap_or(payer);
+    let hook_accounts = accounts_iter.as_slice();
 
-    let new_ix: Instruction<'_, '_, '_, '_> = Instruction {
-        program_id: &stake_ix.program_id.to_bytes(),
-        data: &stake_ix.data,
-        accounts: &account_metas,
+    let config = load_config(config_pda)?;
+    let buyer_record = match flash_sale_settings.tracks_buyers() {
+        true => {
+            let buyer_record_pda = buyer_record_pda.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let buyer_record = load_or_create_buyer_record(payer, recipient.key(), buyer_record_pda, flash_sale_pda)?;
+            // Refunds go to the wallet that funded the record, so a refundable
+            // purchase cannot mix in payment from anyone else.
+            if flash_sale_settings.soft_cap > 0 && buyer_record.payer_address != *payer.key() {
+                msg!("Purchases for this wallet are funded by another payer");
+                return Err(ProgramError::InvalidArgument);
+            }
+            Some((buyer_record_pda, buyer_record))
+        }
+        false => None,
     };
 
-    let expected_deposit_account = find_program_address(
-        &[b"deposit", flash_sale_settings.item_name.as_ref(), token_mint.key(), flash_sale_owner.key()],
-        &crate::id(),
-    );
-
-    let deposit_binding = [expected_deposit_account.1];
-    let deposit_seeds = seeds!(
-        b"deposit",
-        flash_sale_settings.item_name.as_bytes(),
-        token_mint.key(),
-        flash_sale_owner.key(),
-        &deposit_binding
-    );
-
-    invoke_signed(
-        &new_ix,
-        &[
+    let clock = Clock::get()?;
+    flash_sale_settings.check_purchase(&PurchaseCheck {
+        now: clock.unix_timestamp,
+        amount: args.amount,
+        purchased: buyer_record.as_ref().map_or(0, |(_, buyer_record)| buyer_record.purchased),
+        buyer: recipient.key(),
+        payer: payer.key(),
+        referrer: referrer.map(|referrer| referrer.key()),
+        protocol_paused: config.as_ref().is_some_and(|config| config.paused),
+    })?;
+    if clock.unix_timestamp > args.expires_at {
+        msg!("Purchase request has expired");
+        return Err(FlashSaleError::PurchaseExpired.into());
+    }
+
+    let cost = flash_sale_settings.cost_for(args.amount)?;
+    if cost > args.max_total_cost {
+        msg!("Total cost exceeds the buyer's limit");
+        return Err(FlashSaleError::SlippageExceeded.into());
+    }
+
+    // Transfer-fee mints withhold part of every transfer, so the buyer ends up
+    // with less than `amount` leaving the deposit. Vested purchases stay in the
+    // deposit and pay the fee when claimed instead.
+    let transfer_fee = match flash_sale_settings.vests() {
+        true => 0,
+        false => get_transfer_fee(token_mint, args.amount)?.unwrap_or(0),
+    };
+    let net_amount = args.amount - transfer_fee;
+
+    let fee = config.as_ref().map_or(0, |config| config.fee_for(cost));
+    let referral_fee = match referrer {
+        Some(_) => flash_sale_settings.referral_fee_for(cost - fee),
+        None => 0,
+    };
+    let seller_amount = cost - fee - referral_fee;
+    let held_fee = match flash_sale_settings.holds_fees() {
+        true => fee,
+        false => 0,
+    };
+
+    if let Some((buyer_record_pda, mut buyer_record)) = buyer_record {
+        buyer_record.purchased = buyer_record
+            .purchased
+            .checked_add(args.amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        if !flash_sale_settings.vests() {
+            buyer_record.received = buyer_record
+                .received
+                .checked_add(net_amount)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+        buyer_record.paid = buyer_record
+            .paid
+            .checked_add(seller_amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        buyer_record.protocol_fees = buyer_record
+            .protocol_fees
+            .checked_add(held_fee)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        buyer_record.referral_fees = buyer_record
+            .referral_fees
+            .checked_add(referral_fee)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        buyer_record.write_to_slice(&mut buyer_record_pda.try_borrow_mut_data()?)?;
+    }
+
+    if let Some(referrer) = referrer {
+        let referral_record_pda = referral_record_pda.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        record_referral(payer, referrer, referral_record_pda, flash_sale_pda, cost, referral_fee)?;
+    }
+
+    collect_payment(
+        payer,
+        payer_payment_account,
+        flash_sale_pda,
+        proceeds_vault_pda,
+        payment_accounts.as_ref(),
+        seller_amount + referral_fee + held_fee,
+    )?;
+    if let (Some(config), true) = (config.as_ref(), fee > 0 && held_fee == 0) {
+        collect_fee(
+            payer,
+            payer_payment_account,
+            fee_recipient,
+            &config.fee_recipient,
+            payment_accounts.as_ref(),
+            fee,
+        )?;
+    }
+
+    flash_sale_settings.total_sold = flash_sale_settings
+        .total_sold
+        .checked_add(args.amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.total_proceeds = flash_sale_settings
+        .total_proceeds
+        .checked_add(seller_amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.referral_earned = flash_sale_settings
+        .referral_earned
+        .checked_add(referral_fee)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.held_protocol_fees = flash_sale_settings
+        .held_protocol_fees
+        .checked_add(held_fee)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    if !flash_sale_settings.vests() {
+        pinocchio_associated_token_account::instructions::CreateIdempotent {
+            funding_account: payer,
+            account: receiver_token_ata,
+            wallet: recipient,
+            mint: token_mint,
+            system_program,
+            token_program,
+        }
+        .invoke()?;
+        check_token_account_owner(receiver_token_ata, recipient.key())?;
+
+        let seed = flash_sale_settings.seed();
+        let id_seed = flash_sale_settings.id_seed();
+        let expected_deposit_account = find_program_address(
+            &[b"deposit", &seed, token_mint.key(), &flash_sale_settings.owner_address, &id_seed],
+            &crate::id(),
+        );
+
+        let deposit_binding = [expected_deposit_account.1];
+        let deposit_seeds = seeds!(
+            b"deposit",
+            seed.as_slice(),
+            token_mint.key(),
+            &flash_sale_settings.owner_address,
+            id_seed.as_slice(),
+            &deposit_binding
+        );
+
+        transfer_tokens(
+            token_program,
             token_deposit_ata,
             token_mint,
             receiver_token_ata,
             token_deposit_pda,
-            token_program,
-        ],
-        &[Signer::from(&deposit_seeds)]
-    )?;
+            args.amount,
+            get_mint_decimals(token_mint)?,
+            hook_accounts,
+            &[Signer::from(&deposit_seeds)],
+        )?;
+    }
+
+    let mut receipt = [0u8; PURCHASE_RECEIPT_SIZE];
+    PurchaseReceipt {
+        amount: args.amount,
+        net_amount,
+        transfer_fee,
+        cost,
+        protocol_fee: fee,
+        referral_fee,
+    }
+    .write_to_slice(&mut receipt)?;
+    set_return_data(&receipt);
+
+    FlashSaleEvent::Purchase(Purchase {
+        sale: *flash_sale_pda.key(),
+        payer: *payer.key(),
+        recipient: *recipient.key(),
+        referrer: referrer.map_or([0; 32], |referrer| *referrer.key()),
+        amount: args.amount,
+        net_amount,
+        cost,
+        protocol_fee: fee,
+        referral_fee,
+        total_sold: flash_sale_settings.total_sold,
+        timestamp: clock.unix_timestamp,
+    })
+    .emit();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_purchase_args_are_an_error() {
+        let data = 5u64.to_le_bytes();
+        for len in [0, 1, 7] {
+            assert!(GetTokenArgs::try_from(&data[..len]).is_err());
+        }
+        assert_eq!(GetTokenArgs::try_from(&data[..]).unwrap().amount, 5);
+        // The deprecated trailing decimals byte is still accepted.
+        assert_eq!(GetTokenArgs::try_from(&[5, 0, 0, 0, 0, 0, 0, 0, 6][..]).unwrap().amount, 5);
+    }
+}
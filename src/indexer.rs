@@ -0,0 +1,872 @@
+// Offline indexer for flash sale transactions. It works on the JSON returned
+// by `getTransaction` (`json` or `jsonParsed` encoding) and never touches the
+// network, so fixtures captured from any cluster can be replayed as-is.
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use pinocchio::pubkey::Pubkey;
+use serde_json::Value;
+
+use crate::events::{Commitment, FlashSaleEvent, Purchase, SaleClosed, SaleCreated};
+use crate::init_flash_sale::FlashSale;
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+const LOG_TRUNCATED: &str = "Log truncated";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexError {
+    InvalidJson(String),
+    MissingField(&'static str),
+    InvalidEvent(String),
+    InvalidAccount(String),
+    LogsTruncated,
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::InvalidJson(e) => write!(f, "invalid transaction json: {}", e),
+            IndexError::MissingField(field) => write!(f, "missing field `{}`", field),
+            IndexError::InvalidEvent(e) => write!(f, "invalid event: {}", e),
+            IndexError::InvalidAccount(e) => write!(f, "invalid sale account: {}", e),
+            IndexError::LogsTruncated => write!(f, "transaction logs were truncated"),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaleRecord {
+    pub address: String,
+    pub mint: String,
+    pub owner: String,
+    pub item_name: String,
+    pub price: u64,
+    pub price_denominator: u64,
+    pub total_supply: u64,
+    pub end_timestamp: i64,
+    pub payment_mint: Option<String>,
+    pub soft_cap: u64,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PurchaseRecord {
+    pub signature: String,
+    pub slot: u64,
+    pub sale: String,
+    pub payer: String,
+    pub buyer: String,
+    pub referrer: Option<String>,
+    pub amount: u64,
+    pub net_amount: u64,
+    pub cost: u64,
+    pub protocol_fee: u64,
+    pub referral_fee: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseRecord {
+    pub signature: String,
+    pub slot: u64,
+    pub sale: String,
+    pub authority: String,
+    pub remaining_amount: u64,
+    pub total_sold: u64,
+    pub total_proceeds: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitmentRecord {
+    pub signature: String,
+    pub slot: u64,
+    pub sale: String,
+    pub buyer: String,
+    pub amount: u64,
+    pub committed: u64,
+    pub total_committed: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionRecords {
+    pub signature: String,
+    pub slot: u64,
+    pub sales: Vec<SaleRecord>,
+    pub purchases: Vec<PurchaseRecord>,
+    pub closes: Vec<CloseRecord>,
+    pub commitments: Vec<CommitmentRecord>,
+}
+
+fn encode_pubkey(pubkey: &Pubkey) -> String {
+    bs58::encode(pubkey).into_string()
+}
+
+fn optional_pubkey(pubkey: &Pubkey) -> Option<String> {
+    match *pubkey == [0; 32] {
+        true => None,
+        false => Some(encode_pubkey(pubkey)),
+    }
+}
+
+impl From<&SaleCreated> for SaleRecord {
+    fn from(event: &SaleCreated) -> Self {
+        SaleRecord {
+            address: encode_pubkey(&event.sale),
+            mint: encode_pubkey(&event.mint),
+            owner: encode_pubkey(&event.owner),
+            item_name: event.item_name.clone(),
+            price: event.price,
+            price_denominator: event.price_denominator,
+            total_supply: event.total_supply,
+            end_timestamp: event.end_timestamp,
+            payment_mint: optional_pubkey(&event.payment_mint),
+            soft_cap: event.soft_cap,
+            created_at: event.timestamp,
+        }
+    }
+}
+
+impl PurchaseRecord {
+    fn from_event(signature: &str, slot: u64, event: &Purchase) -> Self {
+        PurchaseRecord {
+            signature: signature.to_string(),
+            slot,
+            sale: encode_pubkey(&event.sale),
+            payer: encode_pubkey(&event.payer),
+            buyer: encode_pubkey(&event.recipient),
+            referrer: optional_pubkey(&event.referrer),
+            amount: event.amount,
+            net_amount: event.net_amount,
+            cost: event.cost,
+            protocol_fee: event.protocol_fee,
+            referral_fee: event.referral_fee,
+            timestamp: event.timestamp,
+        }
+    }
+}
+
+impl CloseRecord {
+    fn from_event(signature: &str, slot: u64, event: &SaleClosed) -> Self {
+        CloseRecord {
+            signature: signature.to_string(),
+            slot,
+            sale: encode_pubkey(&event.sale),
+            authority: encode_pubkey(&event.authority),
+            remaining_amount: event.remaining_amount,
+            total_sold: event.total_sold,
+            total_proceeds: event.total_proceeds,
+            timestamp: event.timestamp,
+        }
+    }
+}
+
+impl CommitmentRecord {
+    fn from_event(signature: &str, slot: u64, event: &Commitment) -> Self {
+        CommitmentRecord {
+            signature: signature.to_string(),
+            slot,
+            sale: encode_pubkey(&event.sale),
+            buyer: encode_pubkey(&event.buyer),
+            amount: event.amount,
+            committed: event.committed,
+            total_committed: event.total_committed,
+            timestamp: event.timestamp,
+        }
+    }
+}
+
+// Builds a sale record from the raw data of a flash sale account, for sales
+// created before events were emitted.
+pub fn sale_from_account(address: &Pubkey, data: &[u8]) -> Result<SaleRecord, IndexError> {
+    let flash_sale = FlashSale::try_from(data).map_err(IndexError::InvalidAccount)?;
+
+    Ok(SaleRecord {
+        address: encode_pubkey(address),
+        mint: encode_pubkey(&flash_sale.mint_address),
+        owner: encode_pubkey(&flash_sale.owner_address),
+        item_name: flash_sale.item_name.clone(),
+        price: flash_sale.price,
+        price_denominator: flash_sale.price_denominator,
+        total_supply: flash_sale.total_supply,
+        end_timestamp: flash_sale.end_timestamp,
+        payment_mint: optional_pubkey(&flash_sale.payment_mint),
+        soft_cap: flash_sale.soft_cap,
+        created_at: flash_sale.init_timestamp,
+    })
+}
+
+// Walks the invoke stack in the log messages and decodes every `Program data`
+// line written while this program was the one executing. Data logged by
+// other programs, including ones we CPI into, is skipped.
+pub fn program_events(log_messages: &[String]) -> Result<Vec<FlashSaleEvent>, IndexError> {
+    let program_id = encode_pubkey(&crate::id());
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in log_messages {
+        if line.starts_with(LOG_TRUNCATED) {
+            return Err(IndexError::LogsTruncated);
+        }
+        if let Some(data) = line.strip_prefix(PROGRAM_DATA_PREFIX) {
+            if stack.last() == Some(&program_id.as_str()) {
+                let bytes = STANDARD
+                    .decode(data.trim())
+                    .map_err(|e| IndexError::InvalidEvent(e.to_string()))?;
+                events.push(FlashSaleEvent::try_from(bytes.as_slice()).map_err(IndexError::InvalidEvent)?);
+            }
+            continue;
+        }
+
+        match frame_change(line) {
+            Some(FrameChange::Invoke(program)) => stack.push(program),
+            Some(FrameChange::Exit) => {
+                stack.pop();
+            }
+            None => {}
+        }
+    }
+
+    Ok(events)
+}
+
+enum FrameChange<'a> {
+    Invoke(&'a str),
+    Exit,
+}
+
+// Only `Program <id> invoke [<depth>]`, `Program <id> success` and
+// `Program <id> failed: <error>` change the invoke stack. Program logs such as
+// `Program log: invoke foo` never parse as an id.
+fn frame_change(line: &str) -> Option<FrameChange<'_>> {
+    let (program, status) = line.strip_prefix("Program ")?.split_once(' ')?;
+    if !bs58::decode(program).into_vec().is_ok_and(|bytes| bytes.len() == 32) {
+        return None;
+    }
+    if let Some(depth) = status.strip_prefix("invoke [").and_then(|rest| rest.strip_suffix(']')) {
+        return depth.parse::<u32>().ok().map(|_| FrameChange::Invoke(program));
+    }
+    match status == "success" || status.starts_with("failed: ") {
+        true => Some(FrameChange::Exit),
+        false => None,
+    }
+}
+
+fn account_keys(transaction: &Value) -> Vec<String> {
+    let mut keys: Vec<String> = transaction
+        .pointer("/transaction/message/accountKeys")
+        .and_then(Value::as_array)
+        .map(|keys| {
+            keys.iter()
+                .filter_map(|key| key.as_str().or_else(|| key.get("pubkey").and_then(Value::as_str)))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    for kind in ["writable", "readonly"] {
+        if let Some(loaded) = transaction
+            .pointer(&format!("/meta/loadedAddresses/{}", kind))
+            .and_then(Value::as_array)
+        {
+            keys.extend(loaded.iter().filter_map(Value::as_str).map(str::to_string));
+        }
+    }
+    keys
+}
+
+fn invokes_program(instruction: &Value, keys: &[String], program_id: &str) -> bool {
+    if let Some(id) = instruction.get("programId").and_then(Value::as_str) {
+        return id == program_id;
+    }
+    instruction
+        .get("programIdIndex")
+        .and_then(Value::as_u64)
+        .and_then(|index| keys.get(index as usize))
+        .is_some_and(|key| key == program_id)
+}
+
+// True when this program appears in the top-level or inner instructions.
+pub fn invokes_flash_sale(transaction: &Value) -> bool {
+    let program_id = encode_pubkey(&crate::id());
+    let keys = account_keys(transaction);
+
+    let top_level = transaction
+        .pointer("/transaction/message/instructions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+    let inner = transaction
+        .pointer("/meta/innerInstructions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|group| group.get("instructions").and_then(Value::as_array))
+        .flatten();
+
+    top_level
+        .chain(inner)
+        .any(|instruction| invokes_program(instruction, &keys, &program_id))
+}
+
+pub fn index_transaction(json: &str) -> Result<TransactionRecords, IndexError> {
+    let transaction: Value = serde_json::from_str(json).map_err(|e| IndexError::InvalidJson(e.to_string()))?;
+    index_transaction_value(&transaction)
+}
+
+pub fn index_transaction_value(transaction: &Value) -> Result<TransactionRecords, IndexError> {
+    let signature = transaction
+        .pointer("/transaction/signatures/0")
+        .and_then(Value::as_str)
+        .ok_or(IndexError::MissingField("transaction.signatures"))?
+        .to_string();
+    let slot = transaction
+        .get("slot")
+        .and_then(Value::as_u64)
+        .ok_or(IndexError::MissingField("slot"))?;
+
+    let mut records = TransactionRecords {
+        signature,
+        slot,
+        ..Default::default()
+    };
+
+    // Failed transactions are rolled back, whatever they logged.
+    if transaction.pointer("/meta/err").is_some_and(|err| !err.is_null()) {
+        return Ok(records);
+    }
+
+    let log_messages: Vec<String> = match transaction.pointer("/meta/logMessages").and_then(Value::as_array) {
+        Some(logs) => logs.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        None if invokes_flash_sale(transaction) => return Err(IndexError::LogsTruncated),
+        None => return Ok(records),
+    };
+
+    for event in program_events(&log_messages)? {
+        match &event {
+            FlashSaleEvent::SaleCreated(event) => records.sales.push(SaleRecord::from(event)),
+            FlashSaleEvent::Purchase(event) => records
+                .purchases
+                .push(PurchaseRecord::from_event(&records.signature, slot, event)),
+            FlashSaleEvent::SaleClosed(event) => records
+                .closes
+                .push(CloseRecord::from_event(&records.signature, slot, event)),
+            FlashSaleEvent::Commitment(event) => records
+                .commitments
+                .push(CommitmentRecord::from_event(&records.signature, slot, event)),
+            FlashSaleEvent::SaleUpdated(_) | FlashSaleEvent::Withdrawal(_) => {}
+        }
+    }
+
+    Ok(records)
+}
+
+// Purchases grouped by the wallet that received the tokens.
+pub fn purchases_by_buyer(records: &[TransactionRecords]) -> std::collections::BTreeMap<String, Vec<PurchaseRecord>> {
+    let mut by_buyer: std::collections::BTreeMap<String, Vec<PurchaseRecord>> = std::collections::BTreeMap::new();
+    for purchase in records.iter().flat_map(|record| record.purchases.iter()) {
+        by_buyer.entry(purchase.buyer.clone()).or_default().push(purchase.clone());
+    }
+    by_buyer
+}
+
+#[cfg(feature = "indexer-sqlite")]
+pub mod sqlite {
+    use rusqlite::{params, Connection, OptionalExtension};
+
+    use super::{CloseRecord, CommitmentRecord, PurchaseRecord, SaleRecord, TransactionRecords};
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS sales (
+            signature TEXT NOT NULL,
+            ordinal INTEGER NOT NULL,
+            slot INTEGER NOT NULL,
+            address TEXT NOT NULL,
+            mint TEXT NOT NULL,
+            owner TEXT NOT NULL,
+            item_name TEXT NOT NULL,
+            price INTEGER NOT NULL,
+            price_denominator INTEGER NOT NULL,
+            total_supply INTEGER NOT NULL,
+            end_timestamp INTEGER NOT NULL,
+            payment_mint TEXT,
+            soft_cap INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (signature, ordinal)
+        );
+        CREATE INDEX IF NOT EXISTS sales_by_address ON sales (address, slot);
+        CREATE TABLE IF NOT EXISTS purchases (
+            signature TEXT NOT NULL,
+            ordinal INTEGER NOT NULL,
+            slot INTEGER NOT NULL,
+            sale TEXT NOT NULL,
+            payer TEXT NOT NULL,
+            buyer TEXT NOT NULL,
+            referrer TEXT,
+            amount INTEGER NOT NULL,
+            net_amount INTEGER NOT NULL,
+            cost INTEGER NOT NULL,
+            protocol_fee INTEGER NOT NULL,
+            referral_fee INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            PRIMARY KEY (signature, ordinal)
+        );
+        CREATE INDEX IF NOT EXISTS purchases_by_buyer ON purchases (buyer, sale);
+        CREATE TABLE IF NOT EXISTS closes (
+            signature TEXT NOT NULL,
+            ordinal INTEGER NOT NULL,
+            slot INTEGER NOT NULL,
+            sale TEXT NOT NULL,
+            authority TEXT NOT NULL,
+            remaining_amount INTEGER NOT NULL,
+            total_sold INTEGER NOT NULL,
+            total_proceeds INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            PRIMARY KEY (signature, ordinal)
+        );
+        CREATE INDEX IF NOT EXISTS closes_by_sale ON closes (sale, slot);
+        CREATE TABLE IF NOT EXISTS commitments (
+            signature TEXT NOT NULL,
+            ordinal INTEGER NOT NULL,
+            slot INTEGER NOT NULL,
+            sale TEXT NOT NULL,
+            buyer TEXT NOT NULL,
+            amount INTEGER NOT NULL,
+            committed INTEGER NOT NULL,
+            total_committed INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            PRIMARY KEY (signature, ordinal)
+        );
+        CREATE INDEX IF NOT EXISTS commitments_by_sale ON commitments (sale, slot);
+    ";
+
+    // SQLite integers are signed 64-bit; u64 amounts are stored bit-for-bit.
+    fn to_sql(value: u64) -> i64 {
+        value as i64
+    }
+
+    fn from_sql(value: i64) -> u64 {
+        value as u64
+    }
+
+    pub struct SqliteStore {
+        conn: Connection,
+    }
+
+    impl SqliteStore {
+        pub fn open(path: &str) -> rusqlite::Result<Self> {
+            Self::with_connection(Connection::open(path)?)
+        }
+
+        pub fn open_in_memory() -> rusqlite::Result<Self> {
+            Self::with_connection(Connection::open_in_memory()?)
+        }
+
+        fn with_connection(conn: Connection) -> rusqlite::Result<Self> {
+            conn.execute_batch(SCHEMA)?;
+            Ok(SqliteStore { conn })
+        }
+
+        // Rows are keyed by transaction and position, not by sale address, so
+        // a sale re-created at a closed address keeps both histories.
+        // Inserting the same transaction twice is a no-op, so replaying
+        // overlapping fixture or RPC ranges is safe.
+        pub fn insert(&mut self, records: &TransactionRecords) -> rusqlite::Result<()> {
+            let tx = self.conn.transaction()?;
+            for (ordinal, sale) in records.sales.iter().enumerate() {
+                tx.execute(
+                    "INSERT OR IGNORE INTO sales VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                    params![
+                        records.signature,
+                        ordinal as i64,
+                        to_sql(records.slot),
+                        sale.address,
+                        sale.mint,
+                        sale.owner,
+                        sale.item_name,
+                        to_sql(sale.price),
+                        to_sql(sale.price_denominator),
+                        to_sql(sale.total_supply),
+                        sale.end_timestamp,
+                        sale.payment_mint,
+                        to_sql(sale.soft_cap),
+                        sale.created_at,
+                    ],
+                )?;
+            }
+            for (ordinal, purchase) in records.purchases.iter().enumerate() {
+                tx.execute(
+                    "INSERT OR IGNORE INTO purchases VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    params![
+                        purchase.signature,
+                        ordinal as i64,
+                        to_sql(purchase.slot),
+                        purchase.sale,
+                        purchase.payer,
+                        purchase.buyer,
+                        purchase.referrer,
+                        to_sql(purchase.amount),
+                        to_sql(purchase.net_amount),
+                        to_sql(purchase.cost),
+                        to_sql(purchase.protocol_fee),
+                        to_sql(purchase.referral_fee),
+                        purchase.timestamp,
+                    ],
+                )?;
+            }
+            for (ordinal, close) in records.closes.iter().enumerate() {
+                tx.execute(
+                    "INSERT OR IGNORE INTO closes VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    params![
+                        close.signature,
+                        ordinal as i64,
+                        to_sql(close.slot),
+                        close.sale,
+                        close.authority,
+                        to_sql(close.remaining_amount),
+                        to_sql(close.total_sold),
+                        to_sql(close.total_proceeds),
+                        close.timestamp,
+                    ],
+                )?;
+            }
+            for (ordinal, commitment) in records.commitments.iter().enumerate() {
+                tx.execute(
+                    "INSERT OR IGNORE INTO commitments VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    params![
+                        commitment.signature,
+                        ordinal as i64,
+                        to_sql(commitment.slot),
+                        commitment.sale,
+                        commitment.buyer,
+                        to_sql(commitment.amount),
+                        to_sql(commitment.committed),
+                        to_sql(commitment.total_committed),
+                        commitment.timestamp,
+                    ],
+                )?;
+            }
+            tx.commit()
+        }
+
+        // Every sale created at `address`, oldest first.
+        pub fn sales_at(&self, address: &str) -> rusqlite::Result<Vec<SaleRecord>> {
+            let mut statement = self.conn.prepare(
+                "SELECT address, mint, owner, item_name, price, price_denominator, total_supply,
+                        end_timestamp, payment_mint, soft_cap, created_at
+                 FROM sales WHERE address = ?1 ORDER BY slot, signature, ordinal",
+            )?;
+            let rows = statement.query_map(params![address], |row| {
+                Ok(SaleRecord {
+                    address: row.get(0)?,
+                    mint: row.get(1)?,
+                    owner: row.get(2)?,
+                    item_name: row.get(3)?,
+                    price: from_sql(row.get(4)?),
+                    price_denominator: from_sql(row.get(5)?),
+                    total_supply: from_sql(row.get(6)?),
+                    end_timestamp: row.get(7)?,
+                    payment_mint: row.get(8)?,
+                    soft_cap: from_sql(row.get(9)?),
+                    created_at: row.get(10)?,
+                })
+            })?;
+            rows.collect()
+        }
+
+        // The latest sale created at `address`.
+        pub fn sale(&self, address: &str) -> rusqlite::Result<Option<SaleRecord>> {
+            Ok(self.sales_at(address)?.pop())
+        }
+
+        pub fn purchases_for_buyer(&self, buyer: &str) -> rusqlite::Result<Vec<PurchaseRecord>> {
+            let mut statement = self.conn.prepare(
+                "SELECT signature, slot, sale, payer, buyer, referrer, amount, net_amount, cost,
+                        protocol_fee, referral_fee, timestamp
+                 FROM purchases WHERE buyer = ?1 ORDER BY slot, signature, ordinal",
+            )?;
+            let rows = statement.query_map(params![buyer], |row| {
+                Ok(PurchaseRecord {
+                    signature: row.get(0)?,
+                    slot: from_sql(row.get(1)?),
+                    sale: row.get(2)?,
+                    payer: row.get(3)?,
+                    buyer: row.get(4)?,
+                    referrer: row.get(5)?,
+                    amount: from_sql(row.get(6)?),
+                    net_amount: from_sql(row.get(7)?),
+                    cost: from_sql(row.get(8)?),
+                    protocol_fee: from_sql(row.get(9)?),
+                    referral_fee: from_sql(row.get(10)?),
+                    timestamp: row.get(11)?,
+                })
+            })?;
+            rows.collect()
+        }
+
+        // The latest close of `sale`.
+        pub fn close(&self, sale: &str) -> rusqlite::Result<Option<CloseRecord>> {
+            self.conn
+                .query_row(
+                    "SELECT signature, slot, sale, authority, remaining_amount, total_sold, total_proceeds, timestamp
+                     FROM closes WHERE sale = ?1 ORDER BY slot DESC, signature DESC, ordinal DESC LIMIT 1",
+                    params![sale],
+                    |row| {
+                        Ok(CloseRecord {
+                            signature: row.get(0)?,
+                            slot: from_sql(row.get(1)?),
+                            sale: row.get(2)?,
+                            authority: row.get(3)?,
+                            remaining_amount: from_sql(row.get(4)?),
+                            total_sold: from_sql(row.get(5)?),
+                            total_proceeds: from_sql(row.get(6)?),
+                            timestamp: row.get(7)?,
+                        })
+                    },
+                )
+                .optional()
+        }
+
+        // Every commitment made to `sale`, oldest first.
+        pub fn commitments_for_sale(&self, sale: &str) -> rusqlite::Result<Vec<CommitmentRecord>> {
+            let mut statement = self.conn.prepare(
+                "SELECT signature, slot, sale, buyer, amount, committed, total_committed, timestamp
+                 FROM commitments WHERE sale = ?1 ORDER BY slot, signature, ordinal",
+            )?;
+            let rows = statement.query_map(params![sale], |row| {
+                Ok(CommitmentRecord {
+                    signature: row.get(0)?,
+                    slot: from_sql(row.get(1)?),
+                    sale: row.get(2)?,
+                    buyer: row.get(3)?,
+                    amount: from_sql(row.get(4)?),
+                    committed: from_sql(row.get(5)?),
+                    total_committed: from_sql(row.get(6)?),
+                    timestamp: row.get(7)?,
+                })
+            })?;
+            rows.collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{Withdrawal, WithdrawalKind};
+
+    const SALE: &str = "37jaqCEYykqjQ7ByCmsLdEfRJ8FDMFWN4BMrLB7RPTqt";
+    const OWNER: &str = "67vHA8qZGCJKw1UNGUJZME4MwEWDRGWzp7MGvsut43A8";
+
+    fn fixture(name: &str) -> TransactionRecords {
+        let json = match name {
+            "create_sale" => include_str!("../fixtures/indexer/create_sale.json"),
+            "purchase" => include_str!("../fixtures/indexer/purchase.json"),
+            "close_sale" => include_str!("../fixtures/indexer/close_sale.json"),
+            "failed_purchase" => include_str!("../fixtures/indexer/failed_purchase.json"),
+            _ => unreachable!(),
+        };
+        index_transaction(json).unwrap()
+    }
+
+    #[test]
+    fn create_sale_fixture_yields_the_sale() {
+        let records = fixture("create_sale");
+        assert_eq!(records.slot, 300_000_100);
+        assert_eq!(
+            records.sales,
+            [SaleRecord {
+                address: SALE.to_string(),
+                mint: "FqUwnBMN1shpeqKVm7W5fN73tvrjVr19TQFFgkoFFzhq".to_string(),
+                owner: OWNER.to_string(),
+                item_name: "Widget".to_string(),
+                price: 1_500,
+                price_denominator: 1_000,
+                total_supply: 1_000_000,
+                end_timestamp: 1_760_003_600,
+                payment_mint: None,
+                soft_cap: 0,
+                created_at: 1_760_000_000,
+            }]
+        );
+        assert!(records.purchases.is_empty() && records.closes.is_empty());
+    }
+
+    #[test]
+    fn purchase_fixture_yields_one_row_per_instruction() {
+        let records = fixture("purchase");
+        let [referred, direct] = records.purchases.as_slice() else {
+            panic!("expected two purchases, got {:?}", records.purchases);
+        };
+        assert_eq!(referred.sale, SALE);
+        assert_eq!(referred.buyer, "ErigbMVRXgm2zELSZbAtngkcizM7veFP9PqPUpPAoTpE");
+        assert_eq!(referred.referrer.as_deref(), Some("AKrnJbMTmSN1MkiLKowUVkgrYiCSktHQ8GJS5VwtKQrY"));
+        assert_eq!((referred.amount, referred.cost, referred.protocol_fee, referred.referral_fee), (3_000, 4_500, 45, 222));
+        assert_eq!(direct.buyer, direct.payer);
+        assert_eq!(direct.referrer, None);
+        assert_eq!((direct.amount, direct.cost, direct.protocol_fee, direct.referral_fee), (5_000, 7_500, 75, 0));
+
+        let by_buyer = purchases_by_buyer(std::slice::from_ref(&records));
+        assert_eq!(by_buyer.len(), 2);
+        assert_eq!(by_buyer[&direct.buyer], std::slice::from_ref(direct));
+    }
+
+    #[test]
+    fn close_sale_fixture_yields_the_close() {
+        let records = fixture("close_sale");
+        assert_eq!(
+            records.closes,
+            [CloseRecord {
+                signature: records.signature.clone(),
+                slot: 300_000_300,
+                sale: SALE.to_string(),
+                authority: OWNER.to_string(),
+                remaining_amount: 992_000,
+                total_sold: 8_000,
+                total_proceeds: 11_880,
+                timestamp: 1_760_004_000,
+            }]
+        );
+    }
+
+    #[test]
+    fn failed_purchase_fixture_yields_nothing() {
+        let records = fixture("failed_purchase");
+        assert!(records.sales.is_empty() && records.purchases.is_empty() && records.closes.is_empty());
+    }
+
+    fn data_line(amount: u64) -> String {
+        let event = FlashSaleEvent::Withdrawal(Withdrawal {
+            sale: [1; 32],
+            kind: WithdrawalKind::Proceeds,
+            receiver: [2; 32],
+            amount,
+            timestamp: 0,
+        });
+        format!("{}{}", PROGRAM_DATA_PREFIX, STANDARD.encode(event.to_bytes()))
+    }
+
+    #[test]
+    fn program_logs_do_not_move_the_invoke_stack() {
+        let program = encode_pubkey(&crate::id());
+        let token = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+        let logs = [
+            format!("Program {} invoke [1]", program),
+            "Program log: invoke foo".to_string(),
+            data_line(1),
+            "Program log: success".to_string(),
+            data_line(2),
+            format!("Program {} invoke [2]", token),
+            data_line(3),
+            format!("Program {} success", token),
+            data_line(4),
+            format!("Program {} success", program),
+            data_line(5),
+        ];
+
+        let amounts: Vec<u64> = program_events(&logs)
+            .unwrap()
+            .into_iter()
+            .map(|event| match event {
+                FlashSaleEvent::Withdrawal(withdrawal) => withdrawal.amount,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(amounts, [1, 2, 4]);
+    }
+
+    #[test]
+    fn sale_from_account_decodes_sales_from_before_events() {
+        // The original 116 byte account: name, price, init timestamp, mint and
+        // owner, zero padded.
+        let mut data = vec![0; 116];
+        data[..4].copy_from_slice(&6u32.to_le_bytes());
+        data[4..10].copy_from_slice(b"Widget");
+        data[10..18].copy_from_slice(&1_500u64.to_le_bytes());
+        data[18..26].copy_from_slice(&1_600_000_000i64.to_le_bytes());
+        data[26..58].copy_from_slice(&[3; 32]);
+        data[58..90].copy_from_slice(&[4; 32]);
+
+        let record = sale_from_account(&[1; 32], &data).unwrap();
+        assert_eq!(record.item_name, "Widget");
+        assert_eq!(record.price, 1_500);
+        assert_eq!(record.mint, encode_pubkey(&[3; 32]));
+        assert_eq!(record.owner, encode_pubkey(&[4; 32]));
+        assert_eq!(record.created_at, 1_600_000_000);
+
+        for len in [0, 3, 50, 89] {
+            assert!(matches!(sale_from_account(&[1; 32], &data[..len]), Err(IndexError::InvalidAccount(_))));
+        }
+    }
+
+    #[cfg(feature = "indexer-sqlite")]
+    #[test]
+    fn sqlite_keeps_every_sale_created_at_an_address() {
+        use super::sqlite::SqliteStore;
+
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let created = fixture("create_sale");
+        let closed = fixture("close_sale");
+        let recreated = TransactionRecords {
+            signature: "recreated".to_string(),
+            slot: 300_000_400,
+            sales: vec![SaleRecord {
+                item_name: "Widget v2".to_string(),
+                ..created.sales[0].clone()
+            }],
+            ..Default::default()
+        };
+        for records in [&created, &fixture("purchase"), &closed, &recreated, &created] {
+            store.insert(records).unwrap();
+        }
+
+        let sales = store.sales_at(SALE).unwrap();
+        assert_eq!(sales.len(), 2);
+        assert_eq!(sales[0], created.sales[0]);
+        assert_eq!(store.sale(SALE).unwrap().unwrap().item_name, "Widget v2");
+        assert_eq!(store.close(SALE).unwrap(), Some(closed.closes[0].clone()));
+        assert_eq!(store.purchases_for_buyer(OWNER).unwrap(), []);
+    }
+
+    #[cfg(feature = "indexer-sqlite")]
+    #[test]
+    fn sqlite_keeps_commitments_in_order() {
+        use super::sqlite::SqliteStore;
+
+        let commitment = |signature: &str, slot, amount, total_committed| CommitmentRecord {
+            signature: signature.to_string(),
+            slot,
+            sale: SALE.to_string(),
+            buyer: OWNER.to_string(),
+            amount,
+            committed: amount,
+            total_committed,
+            timestamp: 1_760_000_500,
+        };
+        let records = |signature: &str, slot, commitments| TransactionRecords {
+            signature: signature.to_string(),
+            slot,
+            commitments,
+            ..Default::default()
+        };
+        let later = records("later", 300_000_200, vec![commitment("later", 300_000_200, 30, 60)]);
+        let earlier = records(
+            "earlier",
+            300_000_100,
+            vec![commitment("earlier", 300_000_100, 10, 10), commitment("earlier", 300_000_100, 20, 30)],
+        );
+
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        for records in [&later, &earlier, &later] {
+            store.insert(records).unwrap();
+        }
+        let stored = store.commitments_for_sale(SALE).unwrap();
+        assert_eq!(stored.iter().map(|commitment| commitment.amount).collect::<Vec<_>>(), [10, 20, 30]);
+        assert_eq!(stored[2], later.commitments[0]);
+        assert_eq!(store.commitments_for_sale(OWNER).unwrap(), []);
+    }
+}
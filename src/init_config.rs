@@ -0,0 +1,177 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::instruction::Signer;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::sysvars::rent::Rent;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, seeds, ProgramResult};
+use pinocchio_pubkey::pubkey;
+use shank::{ShankAccount, ShankType};
+
+use crate::error::FlashSaleError;
+use crate::utils::{check_address, BPS_DENOMINATOR, find_program_address};
+
+pub const MAX_FEE_BPS: u16 = BPS_DENOMINATOR;
+
+const BPF_LOADER_UPGRADEABLE_ID: Pubkey = pubkey!("BPFLoaderUpgradeab1e11111111111111111111111");
+
+#[derive(Clone, ShankAccount)]
+pub struct Config {
+    pub admin: Pubkey,
+    pub protocol_fee_bps: u16,
+    pub fee_recipient: Pubkey,
+    pub paused: bool,
+}
+
+pub const CONFIG_ACCOUNT_SIZE: usize = 32 + 2 + 32 + 1;
+
+impl TryFrom<&[u8]> for Config {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < CONFIG_ACCOUNT_SIZE {
+            return Err("Config too small".to_string());
+        }
+        let admin: Pubkey = data[0..32].try_into().unwrap();
+        let protocol_fee_bps = u16::from_le_bytes(data[32..34].try_into().unwrap());
+        let fee_recipient: Pubkey = data[34..66].try_into().unwrap();
+        let paused = data[66] != 0;
+
+        Ok(Config {
+            admin,
+            protocol_fee_bps,
+            fee_recipient,
+            paused,
+        })
+    }
+}
+
+impl Config {
+    pub fn write_to_slice(&self, buf: &mut [u8]) -> Result<(), ProgramError> {
+        buf[0..32].copy_from_slice(&self.admin);
+        buf[32..34].copy_from_slice(&self.protocol_fee_bps.to_le_bytes());
+        buf[34..66].copy_from_slice(&self.fee_recipient);
+        buf[66] = self.paused as u8;
+
+        Ok(())
+    }
+
+    pub fn fee_for(&self, amount: u64) -> u64 {
+        (amount as u128 * self.protocol_fee_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+}
+
+pub fn find_config(config_pda: &AccountInfo) -> Result<u8, ProgramError> {
+    let expected_config_account = find_program_address(&[b"config"], &crate::id());
+    if expected_config_account.0 != *config_pda.key() {
+        msg!("Unexpected config address");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(expected_config_account.1)
+}
+
+pub fn load_config(config_pda: &AccountInfo) -> Result<Option<Config>, ProgramError> {
+    find_config(config_pda)?;
+    if config_pda.owner() != &crate::id() {
+        return Ok(None);
+    }
+
+    let data = config_pda.try_borrow_data()?;
+    Config::try_from(data.as_ref())
+        .map(Some)
+        .map_err(|_| ProgramError::InvalidAccountData)
+}
+
+#[derive(Debug, Clone, ShankType)]
+pub struct InitConfigArgs {
+    pub admin: Pubkey,
+    pub protocol_fee_bps: u16,
+    pub fee_recipient: Pubkey,
+}
+
+impl TryFrom<&[u8]> for InitConfigArgs {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 66 {
+            return Err("Unexpected end of data".to_string());
+        }
+        let admin: Pubkey = data[0..32].try_into().unwrap();
+        let protocol_fee_bps = u16::from_le_bytes(data[32..34].try_into().unwrap());
+        let fee_recipient: Pubkey = data[34..66].try_into().unwrap();
+
+        Ok(InitConfigArgs {
+            admin,
+            protocol_fee_bps,
+            fee_recipient,
+        })
+    }
+}
+
+fn check_upgrade_authority(program_data: &AccountInfo, signer: &AccountInfo) -> ProgramResult {
+    let expected_program_data = find_program_address(&[&crate::id()], &BPF_LOADER_UPGRADEABLE_ID);
+    if expected_program_data.0 != *program_data.key() {
+        msg!("Unexpected program data address");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // ProgramData layout: u32 tag, u64 slot, Option<Pubkey> upgrade authority.
+    let data = program_data.try_borrow_data()?;
+    if data.len() < 45 || data[12] != 1 || data[13..45] != signer.key()[..] {
+        msg!("Signer is not the program upgrade authority");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+    Ok(())
+}
+
+pub fn init_config(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let payer = accounts_iter.next().unwrap();
+    let config_pda = accounts_iter.next().unwrap();
+    let program_data = accounts_iter.next().unwrap();
+    let system_program = accounts_iter.next().unwrap();
+
+    let args = InitConfigArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    if !payer.is_signer(){
+        msg!("Payer must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_address(system_program, pinocchio_system::id())?;
+    check_upgrade_authority(program_data, payer)?;
+
+    if args.protocol_fee_bps > MAX_FEE_BPS {
+        msg!("Protocol fee exceeds 100%");
+        return Err(FlashSaleError::InvalidFee.into());
+    }
+
+    let config_bump = find_config(config_pda)?;
+    if config_pda.owner() == &crate::id() {
+        msg!("Config is already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let config_binding = [config_bump];
+    let config_seeds = seeds!(b"config", &config_binding);
+
+    let rent = Rent::get()?;
+    pinocchio_system::instructions::CreateAccount {
+        from: payer,
+        to: config_pda,
+        space: CONFIG_ACCOUNT_SIZE as u64,
+        lamports: rent.minimum_balance(CONFIG_ACCOUNT_SIZE),
+        owner: &crate::id(),
+    }
+    .invoke_signed(&[Signer::from(&config_seeds)])?;
+
+    Config {
+        admin: args.admin,
+        protocol_fee_bps: args.protocol_fee_bps,
+        fee_recipient: args.fee_recipient,
+        paused: false,
+    }
+    .write_to_slice(&mut config_pda.try_borrow_mut_data()?)?;
+
+    Ok(())
+}
@@ -1,19 +1,29 @@
-use pinocchio::cpi::invoke;
-use pinocchio::instruction::{AccountMeta, Instruction, Signer};
+use pinocchio::instruction::Signer;
 use pinocchio::sysvars::rent::Rent;
 use pinocchio::sysvars::Sysvar;
-use shank::{ShankAccount, ShankInstruction, ShankType};
+use shank::{ShankAccount, ShankType};
 
 use pinocchio::account_info::AccountInfo;
 use pinocchio::program_error::ProgramError;
-use pinocchio::pubkey::{find_program_address, Pubkey};
-use pinocchio::{seeds, ProgramResult};
+use pinocchio::pubkey::Pubkey;
+use pinocchio::{msg, seeds, ProgramResult};
 
 use pinocchio::sysvars::clock::Clock;
 
-use solana_program::pubkey::Pubkey as SPK;
-
-use crate::utils::{check_address, check_address_is_any};
+use crate::buyer_record::BuyerRecord;
+use crate::proceeds_vault::{create_proceeds_vault, PaymentTokenAccounts, ProceedsWithdrawRule};
+use crate::revenue_split::{validate_payees, Payee, Payees, MAX_PAYEES, PAYEE_SIZE};
+use crate::error::FlashSaleError;
+use crate::events::{FlashSaleEvent, SaleCreated};
+use crate::registry::{register_sale, GLOBAL_REGISTRY_OWNER, NOT_REGISTERED};
+use crate::sale_counter::take_sale_id;
+use crate::seeds::{sale_seed, SeedScheme, MAX_NAME_SEED_LENGTH};
+use crate::update_metadata::SaleMetadata;
+use crate::utils::{
+    check_address, check_address_is_any, find_program_address, get_mint_decimals, get_transfer_fee, read_bytes,
+    read_i64, read_pubkey, read_option, read_string, read_u16, read_u32, read_u64, read_u8, transfer_tokens,
+    BPS_DENOMINATOR,
+};
 
 #[derive(Clone, ShankAccount)]
 pub struct FlashSale {
@@ -22,33 +32,118 @@ pub struct FlashSale {
     pub init_timestamp: i64,
     pub mint_address: Pubkey,
     pub owner_address: Pubkey,
+    pub layout_version: u8,
+    pub end_timestamp: i64,
+    pub total_supply: u64,
+    pub total_sold: u64,
+    pub lock_withdrawals: bool,
+    pub max_per_wallet: u64,
+    pub paused: bool,
+    pub operator_address: Pubkey,
+    pub authority_address: Pubkey,
+    pub pending_authority: Pubkey,
+    pub payment_mint: Pubkey,
+    pub withdraw_rule: ProceedsWithdrawRule,
+    pub soft_cap: u64,
+    pub total_proceeds: u64,
+    pub withdrawn_proceeds: u64,
+    pub payee_count: u8,
+    #[idl_type("[Payee; 5]")]
+    pub payees: Payees,
+    pub referral_fee_bps: u16,
+    pub referral_earned: u64,
+    pub referral_claimed: u64,
+    pub price_denominator: u64,
+    pub seed_scheme: SeedScheme,
+    pub sale_id: u64,
+    pub registry_index: u64,
+    pub global_registry_index: u64,
+    pub vesting_cliff: u64,
+    pub vesting_duration: u64,
+    pub total_claimed: u64,
+    pub commit_mode: bool,
+    pub total_committed: u64,
+    pub commit_settled: u64,
+    pub held_protocol_fees: u64,
+    pub metadata: SaleMetadata,
 }
 
-const MAX_NAME_LENGTH: usize = 32;
-const FLASH_SALE_ACCOUNT_SIZE: usize = 4 + MAX_NAME_LENGTH + 8 + 8 + 32 + 32;
+// Display names longer than a seed need a NameHash or SaleId seed scheme.
+const MAX_NAME_LENGTH: usize = 255;
+// Longest a soft-cap sale may run, in seconds, counted from its creation.
+pub const MAX_SOFT_CAP_SALE_DURATION: i64 = 90 * 24 * 60 * 60;
+// Sales created before the layout was versioned only hold the name, price,
+// init timestamp, mint and owner. They decode with defaults that leave them
+// ended, so they can still be closed.
+pub const LEGACY_LAYOUT_VERSION: u8 = 0;
+pub const FLASH_SALE_LAYOUT_VERSION: u8 = 1;
+// Size with a name of up to MAX_NAME_SEED_LENGTH bytes and no metadata; see
+// `account_size`.
+const FLASH_SALE_ACCOUNT_SIZE: usize =
+    4 + MAX_NAME_SEED_LENGTH + 8 + 8 + 32 + 32 + 1 + 8 + 8 + 8 + 1 + 8 + 1 + 32 + 32 + 32 + 32 + 1 + 8 + 8 + 8
+        + 1 + MAX_PAYEES * PAYEE_SIZE + 2 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 8 + 8 + 8;
 
 impl TryFrom<&[u8]> for FlashSale {
     type Error = String;
 
     fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
         let mut offset = 0;
-        let name_len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
-        offset += 4;
-        let item_name = data[offset..offset + name_len]
-            .to_vec()
-            .into_iter()
-            .map(|b| b as char)
-            .collect::<String>();
+        let item_name = read_string(data, &mut offset)?;
+        let price = read_u64(data, &mut offset)?;
+        let init_timestamp = read_i64(data, &mut offset)?;
+        let mint_address = read_pubkey(data, &mut offset)?;
+        let owner_address = read_pubkey(data, &mut offset)?;
 
-        offset += name_len;
+        // Sales created before the layout was versioned end here, followed by
+        // zero padding or the end of the account.
+        let layout_version = data.get(offset).copied().unwrap_or(LEGACY_LAYOUT_VERSION);
+        offset += 1;
+        match layout_version {
+            LEGACY_LAYOUT_VERSION => {
+                return Ok(FlashSale::legacy(item_name, price, init_timestamp, mint_address, owner_address))
+            }
+            FLASH_SALE_LAYOUT_VERSION => {}
+            _ => return Err("Unknown flash sale layout version".to_string()),
+        }
 
-        let price = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
-        offset += 8;
-        let init_timestamp = i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
-        offset += 8;
-        let mint_address: Pubkey = data[offset..offset + 32].try_into().unwrap();
-        offset += 32;
-        let owner_address: Pubkey = data[offset..offset + 32].try_into().unwrap();
+        let end_timestamp = read_i64(data, &mut offset)?;
+        let total_supply = read_u64(data, &mut offset)?;
+        let total_sold = read_u64(data, &mut offset)?;
+        let lock_withdrawals = read_u8(data, &mut offset)? != 0;
+        let max_per_wallet = read_u64(data, &mut offset)?;
+        let paused = read_u8(data, &mut offset)? != 0;
+        let operator_address = read_pubkey(data, &mut offset)?;
+        let authority_address = read_pubkey(data, &mut offset)?;
+        let pending_authority = read_pubkey(data, &mut offset)?;
+        let payment_mint = read_pubkey(data, &mut offset)?;
+        let withdraw_rule = ProceedsWithdrawRule::try_from(read_u8(data, &mut offset)?)?;
+        let soft_cap = read_u64(data, &mut offset)?;
+        let total_proceeds = read_u64(data, &mut offset)?;
+        let withdrawn_proceeds = read_u64(data, &mut offset)?;
+        let payee_count = read_u8(data, &mut offset)?;
+        if payee_count as usize > MAX_PAYEES {
+            return Err("Too many payees".to_string());
+        }
+        let mut payees = [Payee::default(); MAX_PAYEES];
+        for payee in payees.iter_mut() {
+            *payee = Payee::read(read_bytes(data, &mut offset, PAYEE_SIZE)?);
+        }
+        let referral_fee_bps = read_u16(data, &mut offset)?;
+        let referral_earned = read_u64(data, &mut offset)?;
+        let referral_claimed = read_u64(data, &mut offset)?;
+        let price_denominator = read_u64(data, &mut offset)?;
+        let seed_scheme = SeedScheme::try_from(read_u8(data, &mut offset)?)?;
+        let sale_id = read_u64(data, &mut offset)?;
+        let registry_index = read_u64(data, &mut offset)?;
+        let global_registry_index = read_u64(data, &mut offset)?;
+        let vesting_cliff = read_u64(data, &mut offset)?;
+        let vesting_duration = read_u64(data, &mut offset)?;
+        let total_claimed = read_u64(data, &mut offset)?;
+        let commit_mode = read_u8(data, &mut offset)? != 0;
+        let total_committed = read_u64(data, &mut offset)?;
+        let commit_settled = read_u64(data, &mut offset)?;
+        let held_protocol_fees = read_u64(data, &mut offset)?;
+        let metadata = SaleMetadata::read(data, &mut offset)?;
 
         Ok(FlashSale {
             item_name,
@@ -56,15 +151,69 @@ impl TryFrom<&[u8]> for FlashSale {
             init_timestamp,
             mint_address,
             owner_address,
+            layout_version,
+            end_timestamp,
+            total_supply,
+            total_sold,
+            lock_withdrawals,
+            max_per_wallet,
+            paused,
+            operator_address,
+            authority_address,
+            pending_authority,
+            payment_mint,
+            withdraw_rule,
+            soft_cap,
+            total_proceeds,
+            withdrawn_proceeds,
+            payee_count,
+            payees,
+            referral_fee_bps,
+            referral_earned,
+            referral_claimed,
+            price_denominator,
+            seed_scheme,
+            sale_id,
+            registry_index,
+            global_registry_index,
+            vesting_cliff,
+            vesting_duration,
+            total_claimed,
+            commit_mode,
+            total_committed,
+            commit_settled,
+            held_protocol_fees,
+            metadata,
         })
     }
 }
 
+// A purchase as seen by `FlashSale::check_purchase`. `purchased` is what the
+// buyer already bought from the sale.
+pub struct PurchaseCheck<'a> {
+    pub now: i64,
+    pub amount: u64,
+    // What `buyer`, the wallet receiving the tokens, already bought.
+    pub purchased: u64,
+    pub buyer: &'a Pubkey,
+    pub payer: &'a Pubkey,
+    pub referrer: Option<&'a Pubkey>,
+    pub protocol_paused: bool,
+}
+
 impl FlashSale {
     pub fn write_to_slice(&self, buf: &mut [u8]) -> Result<(), ProgramError> {
+        if self.is_legacy() {
+            msg!("Sales created before layout versioning can only be closed");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if buf.len() < self.size() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
         buf[..4].copy_from_slice(&(self.item_name.len() as u32).to_le_bytes());
         let mut offset = 4;
-        buf[offset..offset + self.item_name.len()].copy_from_slice(&self.item_name.as_bytes());
+        buf[offset..offset + self.item_name.len()].copy_from_slice(self.item_name.as_bytes());
         offset += self.item_name.len();
 
         buf[offset..offset + 8].copy_from_slice(&self.price.to_le_bytes());
@@ -74,9 +223,350 @@ impl FlashSale {
         buf[offset..offset + 32].copy_from_slice(&self.mint_address);
         offset += 32;
         buf[offset..offset + 32].copy_from_slice(&self.owner_address);
+        offset += 32;
+        buf[offset] = self.layout_version;
+        offset += 1;
+        buf[offset..offset + 8].copy_from_slice(&self.end_timestamp.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.total_supply.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.total_sold.to_le_bytes());
+        offset += 8;
+        buf[offset] = self.lock_withdrawals as u8;
+        offset += 1;
+        buf[offset..offset + 8].copy_from_slice(&self.max_per_wallet.to_le_bytes());
+        offset += 8;
+        buf[offset] = self.paused as u8;
+        offset += 1;
+        buf[offset..offset + 32].copy_from_slice(&self.operator_address);
+        offset += 32;
+        buf[offset..offset + 32].copy_from_slice(&self.authority_address);
+        offset += 32;
+        buf[offset..offset + 32].copy_from_slice(&self.pending_authority);
+        offset += 32;
+        buf[offset..offset + 32].copy_from_slice(&self.payment_mint);
+        offset += 32;
+        buf[offset] = self.withdraw_rule as u8;
+        offset += 1;
+        buf[offset..offset + 8].copy_from_slice(&self.soft_cap.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.total_proceeds.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.withdrawn_proceeds.to_le_bytes());
+        offset += 8;
+        buf[offset] = self.payee_count;
+        offset += 1;
+        for payee in self.payees.iter() {
+            payee.write(&mut buf[offset..offset + PAYEE_SIZE]);
+            offset += PAYEE_SIZE;
+        }
+        buf[offset..offset + 2].copy_from_slice(&self.referral_fee_bps.to_le_bytes());
+        offset += 2;
+        buf[offset..offset + 8].copy_from_slice(&self.referral_earned.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.referral_claimed.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.price_denominator.to_le_bytes());
+        offset += 8;
+        buf[offset] = self.seed_scheme as u8;
+        offset += 1;
+        buf[offset..offset + 8].copy_from_slice(&self.sale_id.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.registry_index.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.global_registry_index.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.vesting_cliff.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.vesting_duration.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.total_claimed.to_le_bytes());
+        offset += 8;
+        buf[offset] = self.commit_mode as u8;
+        offset += 1;
+        buf[offset..offset + 8].copy_from_slice(&self.total_committed.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.commit_settled.to_le_bytes());
+        offset += 8;
+        buf[offset..offset + 8].copy_from_slice(&self.held_protocol_fees.to_le_bytes());
+        offset += 8;
+        self.metadata.write(buf, &mut offset);
 
         Ok(())
     }
+
+    fn legacy(item_name: String, price: u64, init_timestamp: i64, mint_address: Pubkey, owner_address: Pubkey) -> Self {
+        FlashSale {
+            item_name,
+            price,
+            init_timestamp,
+            mint_address,
+            owner_address,
+            layout_version: LEGACY_LAYOUT_VERSION,
+            end_timestamp: init_timestamp,
+            total_supply: 0,
+            total_sold: 0,
+            lock_withdrawals: false,
+            max_per_wallet: 0,
+            paused: false,
+            operator_address: owner_address,
+            authority_address: owner_address,
+            pending_authority: [0; 32],
+            payment_mint: [0; 32],
+            withdraw_rule: ProceedsWithdrawRule::Immediate,
+            soft_cap: 0,
+            total_proceeds: 0,
+            withdrawn_proceeds: 0,
+            payee_count: 0,
+            payees: [Payee::default(); MAX_PAYEES],
+            referral_fee_bps: 0,
+            referral_earned: 0,
+            referral_claimed: 0,
+            price_denominator: 1,
+            seed_scheme: SeedScheme::Name,
+            sale_id: 0,
+            registry_index: NOT_REGISTERED,
+            global_registry_index: NOT_REGISTERED,
+            vesting_cliff: 0,
+            vesting_duration: 0,
+            total_claimed: 0,
+            commit_mode: false,
+            total_committed: 0,
+            commit_settled: 0,
+            held_protocol_fees: 0,
+            metadata: SaleMetadata::default(),
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.layout_version == LEGACY_LAYOUT_VERSION
+    }
+
+    pub fn account_size(name_len: usize, metadata: &SaleMetadata) -> usize {
+        FLASH_SALE_ACCOUNT_SIZE - MAX_NAME_SEED_LENGTH + name_len.max(MAX_NAME_SEED_LENGTH) + metadata.size()
+    }
+
+    pub fn size(&self) -> usize {
+        Self::account_size(self.item_name.len(), &self.metadata)
+    }
+
+    // Name seed shared by the sale and deposit PDAs, per the sale's seed scheme.
+    pub fn seed(&self) -> Vec<u8> {
+        sale_seed(self.seed_scheme, &self.item_name)
+    }
+
+    // Sale id seed that follows the owner in the sale and deposit PDAs. Legacy
+    // sales were created without one.
+    pub fn id_seed(&self) -> Vec<u8> {
+        match self.is_legacy() {
+            true => Vec::new(),
+            false => self.sale_id.to_le_bytes().to_vec(),
+        }
+    }
+
+    pub fn is_active(&self, now: i64) -> bool {
+        now < self.end_timestamp
+    }
+
+    pub fn pays_in_token(&self) -> bool {
+        self.payment_mint != [0; 32]
+    }
+
+    pub fn soft_cap_reached(&self) -> bool {
+        self.total_proceeds >= self.soft_cap
+    }
+
+    pub fn soft_cap_failed(&self, now: i64) -> bool {
+        self.soft_cap > 0 && !self.is_active(now) && !self.soft_cap_reached()
+    }
+
+    // Whether the vault may pay out proceeds and referral rewards: the
+    // withdraw rule allows it and a soft cap, if any, was reached.
+    pub fn proceeds_unlocked(&self, now: i64) -> bool {
+        let allowed = match self.withdraw_rule {
+            ProceedsWithdrawRule::Immediate => true,
+            ProceedsWithdrawRule::AfterSaleEnd => !self.is_active(now),
+            ProceedsWithdrawRule::SoftCapReached => self.soft_cap_reached(),
+        };
+        allowed && (self.soft_cap == 0 || self.soft_cap_reached())
+    }
+
+    // Soft-cap sales keep the protocol fee in the vault until the cap is
+    // reached, so a refund can return everything the buyer paid.
+    pub fn holds_fees(&self) -> bool {
+        self.soft_cap > 0
+    }
+
+    pub fn payees(&self) -> &[Payee] {
+        &self.payees[..self.payee_count as usize]
+    }
+
+    // `price / price_denominator` is the price of one base unit, so a price per
+    // whole token is `price` over 10^decimals. Rounds up in the seller's favour.
+    pub fn cost_for(&self, amount: u64) -> Result<u64, ProgramError> {
+        let denominator = self.price_denominator.max(1) as u128;
+        let cost = (self.price as u128 * amount as u128).div_ceil(denominator);
+        u64::try_from(cost).map_err(|_| ProgramError::ArithmeticOverflow)
+    }
+
+    pub fn referral_fee_for(&self, amount: u64) -> u64 {
+        (amount as u128 * self.referral_fee_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+
+    pub fn tracks_buyers(&self) -> bool {
+        self.max_per_wallet > 0 || self.soft_cap > 0 || self.vests()
+    }
+
+    // Base units `payment` buys at the sale price, rounded down.
+    pub fn tokens_for(&self, payment: u64) -> u64 {
+        let tokens = payment as u128 * self.price_denominator.max(1) as u128 / self.price.max(1) as u128;
+        tokens.min(u64::MAX as u128) as u64
+    }
+
+    // Commit sales: what a commitment of `committed` receives once the sale
+    // has ended, min(requested, pro rata share of the supply).
+    pub fn commit_allocation(&self, committed: u64) -> u64 {
+        if self.total_committed == 0 {
+            return 0;
+        }
+        let share = self.total_supply as u128 * committed as u128 / self.total_committed as u128;
+        self.tokens_for(committed).min(share as u64)
+    }
+
+    // Terms every sale must keep, checked at init and again on every update.
+    pub fn check_terms(&self) -> ProgramResult {
+        if self.price == 0 {
+            msg!("Price must not be zero");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.price_denominator == 0 {
+            msg!("Price denominator must not be zero");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.referral_fee_bps > BPS_DENOMINATOR {
+            msg!("Referral fee exceeds 100%");
+            return Err(FlashSaleError::InvalidFee.into());
+        }
+        if self.vesting_duration > 0 && self.vesting_cliff > self.vesting_duration {
+            msg!("Vesting cliff exceeds the vesting duration");
+            return Err(ProgramError::InvalidArgument);
+        }
+        // Commitments only turn into sales after the end, so a soft cap could
+        // never be checked while the sale runs.
+        if self.commit_mode && self.soft_cap > 0 {
+            msg!("Commit sales cannot have a soft cap");
+            return Err(ProgramError::InvalidArgument);
+        }
+        // Buyers' payments stay locked until a soft-cap sale ends, so it
+        // cannot run, or be extended, past a fixed bound.
+        if self.soft_cap > 0 && self.end_timestamp.saturating_sub(self.init_timestamp) > MAX_SOFT_CAP_SALE_DURATION {
+            msg!("Soft-cap sales cannot run longer than MAX_SOFT_CAP_SALE_DURATION");
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    // The checks shared by get_token and quote, so a quote that says the
+    // caller can buy matches what the purchase itself accepts.
+    pub fn check_purchase(&self, purchase: &PurchaseCheck) -> ProgramResult {
+        if self.commit_mode {
+            msg!("Commit sales take commitments instead of purchases");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if purchase.protocol_paused {
+            msg!("Protocol is paused");
+            return Err(FlashSaleError::ProtocolPaused.into());
+        }
+        if self.paused {
+            msg!("Sale is paused");
+            return Err(FlashSaleError::SalePaused.into());
+        }
+        if !self.is_active(purchase.now) {
+            msg!("Sale has ended");
+            return Err(FlashSaleError::SaleEnded.into());
+        }
+        if purchase.amount == 0 {
+            msg!("Amount must be greater than zero");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if purchase.amount > self.remaining_supply() {
+            msg!("Not enough tokens left in the sale");
+            return Err(FlashSaleError::InsufficientSupply.into());
+        }
+        if self.max_per_wallet > 0 && purchase.purchased.saturating_add(purchase.amount) > self.max_per_wallet {
+            msg!("Purchase exceeds the per-wallet limit");
+            return Err(ProgramError::InvalidArgument);
+        }
+        // Neither side of a purchase made for someone else may collect the
+        // reward by referring it.
+        if purchase.referrer == Some(purchase.payer) || purchase.referrer == Some(purchase.buyer) {
+            msg!("Buyers cannot refer themselves");
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    // Supply still for sale. Vested purchases stay in the deposit, so the
+    // deposit balance alone cannot stop overselling.
+    pub fn remaining_supply(&self) -> u64 {
+        self.total_supply.saturating_sub(self.total_sold)
+    }
+
+    // Commit sales split the supply pro rata as buyers claim after the end, so
+    // the supply is fixed from then on.
+    pub fn supply_frozen(&self, now: i64) -> bool {
+        self.commit_mode && !self.is_active(now)
+    }
+
+    // Supply that can no longer be withdrawn as unsold.
+    pub fn reserved_supply(&self) -> u64 {
+        match self.commit_mode {
+            true => self.tokens_for(self.total_committed).min(self.total_supply).max(self.total_sold),
+            false => self.total_sold,
+        }
+    }
+
+    // Tokens a refunded buyer sends back to the deposit. Vested purchases
+    // never left it, and claims are closed once the soft cap is missed.
+    pub fn returned_tokens(&self, buyer_record: &BuyerRecord) -> u64 {
+        match self.vests() {
+            true => 0,
+            false => buyer_record.received,
+        }
+    }
+
+    // Takes a refunded buyer's purchases back out of the sale. What the mint
+    // withheld on the way to the buyer and on the way back (`return_fee`)
+    // never returns to the deposit, so it leaves the supply for good.
+    pub fn refund_purchase(&mut self, buyer_record: &BuyerRecord, return_fee: u64) {
+        let withheld = match self.vests() {
+            true => 0,
+            false => buyer_record.purchased.saturating_sub(buyer_record.received),
+        };
+        self.total_sold = self.total_sold.saturating_sub(buyer_record.purchased);
+        self.total_proceeds = self.total_proceeds.saturating_sub(buyer_record.paid);
+        self.held_protocol_fees = self.held_protocol_fees.saturating_sub(buyer_record.protocol_fees);
+        self.referral_earned = self.referral_earned.saturating_sub(buyer_record.referral_fees);
+        self.total_supply = self.total_supply.saturating_sub(withheld + return_fee);
+    }
+
+    // Vesting sales keep purchased tokens in the deposit until claimed.
+    pub fn vests(&self) -> bool {
+        self.vesting_cliff > 0 || self.vesting_duration > 0
+    }
+
+    // Part of `purchased` released by `now`. Vesting starts when the sale
+    // ends: nothing before the cliff, then linear over `vesting_duration`.
+    pub fn vested_amount(&self, purchased: u64, now: i64) -> u64 {
+        let elapsed = now.saturating_sub(self.end_timestamp);
+        if elapsed < 0 || (elapsed as u64) < self.vesting_cliff {
+            return 0;
+        }
+        if elapsed as u64 >= self.vesting_duration {
+            return purchased;
+        }
+        (purchased as u128 * elapsed as u128 / self.vesting_duration as u128) as u64
+    }
 }
 
 #[derive(Debug, Clone, ShankType)]
@@ -84,34 +574,99 @@ pub struct InitFlashSaleArgs {
     pub initial_price: u64,
     pub sale_duration: u64,
     pub amount: u64,
-    pub decimals: u8,
     pub item_name: String,
+    pub lock_withdrawals: bool,
+    pub max_per_wallet: u64,
+    pub payment_mint: Option<Pubkey>,
+    pub withdraw_rule: ProceedsWithdrawRule,
+    pub soft_cap: u64,
+    pub payees: Vec<Payee>,
+    pub referral_fee_bps: u16,
+    pub price_denominator: u64,
+    pub seed_scheme: SeedScheme,
+    pub sale_id: u64,
+    pub metadata: SaleMetadata,
+    pub vesting_cliff: u64,
+    pub vesting_duration: u64,
+    pub commit_mode: bool,
 }
 
 impl TryFrom<&[u8]> for InitFlashSaleArgs {
     type Error = String;
 
     fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
-        let initial_price = u64::from_le_bytes(data[0..8].try_into().unwrap());
-        let sale_duration = u64::from_le_bytes(data[8..16].try_into().unwrap());
-        let amount = u64::from_le_bytes(data[16..24].try_into().unwrap());
-        let decimals = u8::from_le_bytes(data[24..25].try_into().unwrap());
+        let mut offset = 0;
+        let initial_price = read_u64(data, &mut offset)?;
+        let sale_duration = read_u64(data, &mut offset)?;
+        let amount = read_u64(data, &mut offset)?;
 
-        let name_len = u32::from_le_bytes(data[25..29].try_into().unwrap()) as usize;
+        // Deprecated layout: older clients send a decimals byte after `amount`
+        // and end the data with the item name. A current layout can never
+        // match, since its name length is followed by at least 72 more bytes.
+        // The byte is skipped; decimals always come from the mint.
+        let mut deprecated_offset = offset + 1;
+        let deprecated = read_u32(data, &mut deprecated_offset)
+            .is_ok_and(|name_len| data.len() == deprecated_offset + name_len as usize);
+        if deprecated {
+            msg!("Deprecated InitFlashSale format: decimals are read from the mint");
+            offset += 1;
+        }
 
-        let item_name = data[29..29 + name_len]
-            .to_vec()
-            .into_iter()
-            .map(|b| b as char)
-            .collect::<String>();
+        let item_name = read_string(data, &mut offset)?;
+        let name_len = item_name.len();
+        if name_len > MAX_NAME_LENGTH {
+            return Err("Item name too long".to_string());
+        }
 
-        Ok(InitFlashSaleArgs {
+        let mut args = InitFlashSaleArgs {
             initial_price,
             sale_duration,
             amount,
-            decimals,
             item_name,
-        })
+            lock_withdrawals: false,
+            max_per_wallet: 0,
+            payment_mint: None,
+            withdraw_rule: ProceedsWithdrawRule::Immediate,
+            soft_cap: 0,
+            payees: Vec::new(),
+            referral_fee_bps: 0,
+            price_denominator: 1,
+            seed_scheme: SeedScheme::Name,
+            sale_id: 0,
+            metadata: SaleMetadata::default(),
+            vesting_cliff: 0,
+            vesting_duration: 0,
+            commit_mode: false,
+        };
+
+        if !deprecated {
+            args.lock_withdrawals = read_u8(data, &mut offset)? != 0;
+            args.max_per_wallet = read_u64(data, &mut offset)?;
+            args.payment_mint = read_option(data, &mut offset, 32)?.map(|mint| -> Pubkey { mint.try_into().unwrap() });
+            args.withdraw_rule = ProceedsWithdrawRule::try_from(read_u8(data, &mut offset)?)?;
+            args.soft_cap = read_u64(data, &mut offset)?;
+            let payee_count = read_u32(data, &mut offset)? as usize;
+            if payee_count > MAX_PAYEES {
+                return Err("Too many payees".to_string());
+            }
+            args.payees = (0..payee_count)
+                .map(|_| read_bytes(data, &mut offset, PAYEE_SIZE).map(Payee::read))
+                .collect::<Result<Vec<Payee>, String>>()?;
+            args.referral_fee_bps = read_u16(data, &mut offset)?;
+            args.price_denominator = read_u64(data, &mut offset)?;
+            args.seed_scheme = SeedScheme::try_from(read_u8(data, &mut offset)?)?;
+            args.sale_id = read_u64(data, &mut offset)?;
+            args.metadata = SaleMetadata::read(data, &mut offset)?;
+            args.vesting_cliff = read_u64(data, &mut offset)?;
+            args.vesting_duration = read_u64(data, &mut offset)?;
+            args.commit_mode = read_u8(data, &mut offset)? != 0;
+        }
+
+        if args.seed_scheme == SeedScheme::Name && name_len > MAX_NAME_SEED_LENGTH {
+            return Err("Item name too long to be used as a seed".to_string());
+        }
+
+        Ok(args)
     }
 }
 
@@ -127,25 +682,36 @@ pub fn init_flash_sale(accounts: &[AccountInfo], instruction_data: &[u8]) -> Pro
     let system_program = accounts_iter.next().unwrap();
     let token_program = accounts_iter.next().unwrap();
     let associated_token_program = accounts_iter.next().unwrap();
+    let _sysvar_clock = accounts_iter.next();
+    let _sysvar_rent = accounts_iter.next();
+    let proceeds_vault_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
 
     let args = InitFlashSaleArgs::try_from(instruction_data)
         .map_err(|_| ProgramError::InvalidInstructionData)?;
+    validate_payees(&args.payees)?;
 
     check_address(system_program, pinocchio_system::id())?;
     check_address(associated_token_program, pinocchio_associated_token_account::id())?;
     check_address_is_any(token_program, &[pinocchio_token::id(), spl_token_2022::id().to_bytes()])?;
 
+    let seed = sale_seed(args.seed_scheme, &args.item_name);
+    let id_seed = args.sale_id.to_le_bytes();
     let expected_deposit_account = find_program_address(
-        &[b"deposit", args.item_name.as_ref(), token_mint.key(), owner.key()],
+        &[b"deposit", &seed, token_mint.key(), owner.key(), &id_seed],
         &crate::id(),
     );
+    if expected_deposit_account.0 != *token_deposit_pda.key() {
+        msg!("Unexpected deposit address");
+        return Err(ProgramError::InvalidSeeds);
+    }
 
     let deposit_binding = [expected_deposit_account.1];
     let stake_seeds = seeds!(
         b"deposit",
-        args.item_name.as_bytes(),
+        seed.as_slice(),
         token_mint.key(),
         owner.key(),
+        &id_seed,
         &deposit_binding
     );
 
@@ -166,89 +732,395 @@ pub fn init_flash_sale(accounts: &[AccountInfo], instruction_data: &[u8]) -> Pro
     let clock = Clock::get().unwrap();
 
     let rent = Rent::get().unwrap();
-    let minimum_balance = rent.minimum_balance(FLASH_SALE_ACCOUNT_SIZE as usize);
+    let account_size = FlashSale::account_size(args.item_name.len(), &args.metadata);
+    let minimum_balance = rent.minimum_balance(account_size);
 
     let expected_sale_account = find_program_address(
-        &[b"sale", args.item_name.as_ref(), token_mint.key(), owner.key()],
+        &[b"sale", &seed, token_mint.key(), owner.key(), &id_seed],
         &crate::id(),
     );
+    if expected_sale_account.0 != *flash_sale_pda.key() {
+        msg!("Unexpected flash sale address");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if flash_sale_pda.owner() == &crate::id() || flash_sale_pda.data_len() > 0 {
+        msg!("Flash sale account is already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
 
     let sale_binding = [expected_sale_account.1];
     let sale_seeds = seeds!(
         b"sale",
-        args.item_name.as_bytes(),
+        seed.as_slice(),
         token_mint.key(),
         owner.key(),
+        &id_seed,
         &sale_binding
     );
 
-    if flash_sale_pda.owner() == &crate::id(){
-        pinocchio_system::instructions::Transfer {
-            from: owner,
-            to: flash_sale_pda,
-            lamports: minimum_balance,
-        }
-        .invoke()?;
-    }
-    else{
-        pinocchio_system::instructions::CreateAccount {
-            from: owner,
-            to: flash_sale_pda,
-            space: FLASH_SALE_ACCOUNT_SIZE as u64,
-            lamports: minimum_balance,
-            owner: &crate::id(),
-        }
-        .invoke_signed(&[Signer::from(&sale_seeds)])?;
+    pinocchio_system::instructions::CreateAccount {
+        from: owner,
+        to: flash_sale_pda,
+        space: account_size as u64,
+        lamports: minimum_balance,
+        owner: &crate::id(),
     }
+    .invoke_signed(&[Signer::from(&sale_seeds)])?;
 
     let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
 
-    FlashSale {
+    let mut flash_sale_settings = FlashSale {
         item_name: args.item_name.clone(),
         price: args.initial_price,
         init_timestamp: clock.unix_timestamp,
         mint_address: *token_mint.key(),
         owner_address: *owner.key(),
-    }
-    .write_to_slice(&mut flash_sale_data)?;
-
-    let stake_ix = spl_token_2022::instruction::transfer_checked(
-        &SPK::new_from_array(*token_program.key()),
-        &SPK::new_from_array(*source_token_account.key()),
-        &SPK::new_from_array(*token_mint.key()),
-        &SPK::new_from_array(*token_deposit_ata.key()),
-        &SPK::new_from_array(*owner.key()),
-        &[],
-        args.amount,
-        args.decimals,
-    )
-    .unwrap();
-
-    let account_metas: Vec<AccountMeta> = stake_ix
-        .accounts
-        .iter()
-        .map(|m| AccountMeta {
-            is_signer: m.is_signer,
-            is_writable: m.is_writable,
-            pubkey: m.pubkey.as_array(),
-        })
-        .collect();
-
-    let new_ix: Instruction<'_, '_, '_, '_> = Instruction {
-        program_id: &stake_ix.program_id.to_bytes(),
-        data: &stake_ix.data,
-        accounts: &account_metas,
+        layout_version: FLASH_SALE_LAYOUT_VERSION,
+        end_timestamp: clock.unix_timestamp.saturating_add(args.sale_duration as i64),
+        total_supply: args.amount - get_transfer_fee(token_mint, args.amount)?.unwrap_or(0),
+        total_sold: 0,
+        lock_withdrawals: args.lock_withdrawals,
+        max_per_wallet: args.max_per_wallet,
+        paused: false,
+        operator_address: *owner.key(),
+        authority_address: *owner.key(),
+        pending_authority: [0; 32],
+        payment_mint: args.payment_mint.unwrap_or([0; 32]),
+        withdraw_rule: args.withdraw_rule,
+        soft_cap: args.soft_cap,
+        total_proceeds: 0,
+        withdrawn_proceeds: 0,
+        payee_count: args.payees.len() as u8,
+        payees: {
+            let mut payees = [Payee::default(); MAX_PAYEES];
+            payees[..args.payees.len()].copy_from_slice(&args.payees);
+            payees
+        },
+        referral_fee_bps: args.referral_fee_bps,
+        referral_earned: 0,
+        referral_claimed: 0,
+        price_denominator: args.price_denominator,
+        seed_scheme: args.seed_scheme,
+        sale_id: args.sale_id,
+        registry_index: NOT_REGISTERED,
+        global_registry_index: NOT_REGISTERED,
+        vesting_cliff: args.vesting_cliff,
+        vesting_duration: args.vesting_duration,
+        total_claimed: 0,
+        commit_mode: args.commit_mode,
+        total_committed: 0,
+        commit_settled: 0,
+        held_protocol_fees: 0,
+        metadata: args.metadata.clone(),
     };
+    flash_sale_settings.check_terms()?;
+
+    let payment_accounts = PaymentTokenAccounts::from_iter(&flash_sale_settings, accounts_iter)?;
+    let registry_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let registry_page_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    // Listing in the global registry is opt-in; pass the program id for both
+    // accounts to skip it.
+    let global_registry_pda = accounts_iter.next().filter(|account| *account.key() != crate::id());
+    let global_registry_page_pda = accounts_iter.next().filter(|account| *account.key() != crate::id());
+    let sale_counter_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let hook_accounts = accounts_iter.as_slice();
 
-    invoke(
-        &new_ix,
-        &[
-            source_token_account,
-            token_mint,
-            token_deposit_ata,
+    take_sale_id(owner, owner.key(), sale_counter_pda, args.sale_id)?;
+    flash_sale_settings.registry_index =
+        register_sale(owner, owner.key(), registry_pda, registry_page_pda, flash_sale_pda.key())?;
+    if let (Some(global_registry_pda), Some(global_registry_page_pda)) = (global_registry_pda, global_registry_page_pda) {
+        flash_sale_settings.global_registry_index = register_sale(
             owner,
-            token_program,
-        ],
+            &GLOBAL_REGISTRY_OWNER,
+            global_registry_pda,
+            global_registry_page_pda,
+            flash_sale_pda.key(),
+        )?;
+    }
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+    create_proceeds_vault(
+        owner,
+        flash_sale_pda,
+        proceeds_vault_pda,
+        system_program,
+        payment_accounts.as_ref(),
+    )?;
+
+    transfer_tokens(
+        token_program,
+        source_token_account,
+        token_mint,
+        token_deposit_ata,
+        owner,
+        args.amount,
+        get_mint_decimals(token_mint)?,
+        hook_accounts,
+        &[],
     )?;
+
+    FlashSaleEvent::SaleCreated(SaleCreated {
+        sale: *flash_sale_pda.key(),
+        mint: flash_sale_settings.mint_address,
+        owner: flash_sale_settings.owner_address,
+        item_name: flash_sale_settings.item_name.clone(),
+        price: flash_sale_settings.price,
+        price_denominator: flash_sale_settings.price_denominator,
+        total_supply: flash_sale_settings.total_supply,
+        end_timestamp: flash_sale_settings.end_timestamp,
+        payment_mint: flash_sale_settings.payment_mint,
+        soft_cap: flash_sale_settings.soft_cap,
+        timestamp: clock.unix_timestamp,
+    })
+    .emit();
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // A live sale running from 1_000 to 2_000 with 250 of its 1_000 tokens sold.
+    pub fn sale(item_name: &str) -> FlashSale {
+        FlashSale {
+            layout_version: FLASH_SALE_LAYOUT_VERSION,
+            end_timestamp: 2_000,
+            total_supply: 1_000,
+            total_sold: 250,
+            registry_index: 3,
+            sale_id: 3,
+            seed_scheme: SeedScheme::SaleId,
+            metadata: SaleMetadata {
+                description: "A sale".to_string(),
+                ..SaleMetadata::default()
+            },
+            ..FlashSale::legacy(item_name.to_string(), 10, 1_000, [1; 32], [2; 32])
+        }
+    }
+
+    pub fn sale_data(sale: &FlashSale) -> Vec<u8> {
+        let mut data = vec![0; sale.size()];
+        sale.write_to_slice(&mut data).unwrap();
+        data
+    }
+
+    // Account data as written by the original program: a 116 byte account
+    // with the name padded to 32 bytes.
+    fn legacy_account(item_name: &str) -> Vec<u8> {
+        let mut data = vec![0; 116];
+        data[..4].copy_from_slice(&(item_name.len() as u32).to_le_bytes());
+        let mut offset = 4;
+        data[offset..offset + item_name.len()].copy_from_slice(item_name.as_bytes());
+        offset += item_name.len();
+        data[offset..offset + 8].copy_from_slice(&10u64.to_le_bytes());
+        data[offset + 8..offset + 16].copy_from_slice(&1_000i64.to_le_bytes());
+        data[offset + 16..offset + 48].copy_from_slice(&[1; 32]);
+        data[offset + 48..offset + 80].copy_from_slice(&[2; 32]);
+        data
+    }
+
+    fn init_args(item_name: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&10u64.to_le_bytes());
+        data.extend_from_slice(&3_600u64.to_le_bytes());
+        data.extend_from_slice(&1_000u64.to_le_bytes());
+        data.extend_from_slice(&(item_name.len() as u32).to_le_bytes());
+        data.extend_from_slice(item_name.as_bytes());
+        data.push(1); // lock_withdrawals
+        data.extend_from_slice(&5u64.to_le_bytes());
+        data.push(0); // payment_mint
+        data.push(ProceedsWithdrawRule::AfterSaleEnd as u8);
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&1_000u64.to_le_bytes());
+        data.push(SeedScheme::Name as u8);
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&[0; 13]); // empty metadata
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.push(0);
+        data
+    }
+
+    fn deprecated_init_args(item_name: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&10u64.to_le_bytes());
+        data.extend_from_slice(&3_600u64.to_le_bytes());
+        data.extend_from_slice(&1_000u64.to_le_bytes());
+        data.push(9);
+        data.extend_from_slice(&(item_name.len() as u32).to_le_bytes());
+        data.extend_from_slice(item_name.as_bytes());
+        data
+    }
+
+    #[test]
+    fn sale_round_trips() {
+        let sale = sale("Concert ticket");
+        let mut data = vec![0; sale.size()];
+        sale.write_to_slice(&mut data).unwrap();
+
+        let decoded = FlashSale::try_from(data.as_slice()).unwrap();
+        assert_eq!(decoded.item_name, "Concert ticket");
+        assert_eq!(decoded.layout_version, FLASH_SALE_LAYOUT_VERSION);
+        assert_eq!(decoded.total_sold, 250);
+        assert_eq!(decoded.registry_index, 3);
+        assert_eq!(decoded.seed_scheme, SeedScheme::SaleId);
+        assert!(decoded.seed().is_empty());
+        assert_eq!(decoded.id_seed(), 3u64.to_le_bytes());
+        assert_eq!(decoded.metadata, sale.metadata);
+    }
+
+    #[test]
+    fn legacy_sale_decodes_as_ended() {
+        for name in ["", "Ticket", "A name of exactly 32 bytes long!"] {
+            let sale = FlashSale::try_from(legacy_account(name).as_slice()).unwrap();
+            assert!(sale.is_legacy());
+            assert_eq!(sale.item_name, name);
+            assert_eq!(sale.price, 10);
+            assert_eq!(sale.authority_address, [2; 32]);
+            assert_eq!(sale.registry_index, NOT_REGISTERED);
+            assert!(!sale.is_active(sale.init_timestamp));
+            assert_eq!(sale.seed(), name.as_bytes());
+            assert!(sale.id_seed().is_empty());
+        }
+    }
+
+    #[test]
+    fn legacy_sale_is_not_rewritten() {
+        let sale = FlashSale::try_from(legacy_account("Ticket").as_slice()).unwrap();
+        let mut data = vec![0; sale.size()];
+        assert_eq!(sale.write_to_slice(&mut data), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn short_or_unknown_data_is_an_error() {
+        let sale = sale("Ticket");
+        let mut data = vec![0; sale.size()];
+        sale.write_to_slice(&mut data).unwrap();
+
+        // The name is padded to MAX_NAME_SEED_LENGTH, so the data ends before
+        // the account does.
+        let data_len = data.len() - (MAX_NAME_SEED_LENGTH - "Ticket".len());
+        for len in [0, 3, 50, 91, 200, data_len - 1] {
+            assert!(FlashSale::try_from(&data[..len]).is_err());
+        }
+        let version_offset = 4 + 6 + 8 + 8 + 32 + 32;
+        data[version_offset] = FLASH_SALE_LAYOUT_VERSION + 1;
+        assert!(FlashSale::try_from(data.as_slice()).is_err());
+    }
+
+    #[test]
+    fn write_needs_the_full_account_size() {
+        let sale = sale("Ticket");
+        let mut data = vec![0; sale.size() - 1];
+        assert_eq!(sale.write_to_slice(&mut data), Err(ProgramError::AccountDataTooSmall));
+    }
+
+    #[test]
+    fn init_args_keep_empty_names_in_either_layout() {
+        let args = InitFlashSaleArgs::try_from(init_args("").as_slice()).unwrap();
+        assert_eq!(args.item_name, "");
+        assert!(args.lock_withdrawals);
+        assert_eq!(args.max_per_wallet, 5);
+        assert_eq!(args.price_denominator, 1_000);
+
+        let args = InitFlashSaleArgs::try_from(deprecated_init_args("").as_slice()).unwrap();
+        assert_eq!(args.item_name, "");
+        assert_eq!(args.amount, 1_000);
+        assert_eq!(args.price_denominator, 1);
+    }
+
+    #[test]
+    fn deprecated_init_args_skip_the_decimals_byte() {
+        let args = InitFlashSaleArgs::try_from(deprecated_init_args("Ticket").as_slice()).unwrap();
+        assert_eq!(args.item_name, "Ticket");
+        assert_eq!(args.seed_scheme, SeedScheme::Name);
+        assert!(!args.lock_withdrawals);
+
+        let args = InitFlashSaleArgs::try_from(init_args("Ticket").as_slice()).unwrap();
+        assert_eq!(args.item_name, "Ticket");
+        assert_eq!(args.withdraw_rule, ProceedsWithdrawRule::AfterSaleEnd);
+    }
+
+    #[test]
+    fn purchases_are_checked_against_supply_wallet_limit_and_referrer() {
+        let sale = FlashSale {
+            end_timestamp: 100,
+            total_supply: 1_000,
+            total_sold: 900,
+            max_per_wallet: 150,
+            ..sale("Ticket")
+        };
+        let buyer = [3; 32];
+        let referrer = [4; 32];
+        let purchase = |amount, purchased, referrer| PurchaseCheck {
+            now: 50,
+            amount,
+            purchased,
+            buyer: &buyer,
+            payer: &buyer,
+            referrer,
+            protocol_paused: false,
+        };
+
+        assert!(sale.check_purchase(&purchase(100, 50, Some(&referrer))).is_ok());
+        assert_eq!(
+            sale.check_purchase(&purchase(0, 0, None)),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            sale.check_purchase(&purchase(101, 0, None)),
+            Err(FlashSaleError::InsufficientSupply.into())
+        );
+        assert_eq!(
+            sale.check_purchase(&purchase(100, 51, None)),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            sale.check_purchase(&purchase(100, 0, Some(&buyer))),
+            Err(ProgramError::InvalidArgument)
+        );
+        let payer = [6; 32];
+        let gift = |referrer| PurchaseCheck { payer: &payer, ..purchase(100, 0, referrer) };
+        assert!(sale.check_purchase(&gift(Some(&referrer))).is_ok());
+        assert_eq!(sale.check_purchase(&gift(Some(&buyer))), Err(ProgramError::InvalidArgument));
+        assert_eq!(sale.check_purchase(&gift(Some(&payer))), Err(ProgramError::InvalidArgument));
+        assert_eq!(
+            sale.check_purchase(&PurchaseCheck { now: 100, ..purchase(1, 0, None) }),
+            Err(FlashSaleError::SaleEnded.into())
+        );
+        assert_eq!(
+            sale.check_purchase(&PurchaseCheck { protocol_paused: true, ..purchase(1, 0, None) }),
+            Err(FlashSaleError::ProtocolPaused.into())
+        );
+    }
+
+    #[test]
+    fn sale_terms_reject_a_zero_price_and_open_ended_soft_cap_sales() {
+        let sale = sale("Ticket");
+        assert!(sale.check_terms().is_ok());
+        assert!(FlashSale { price: 0, ..sale.clone() }.check_terms().is_err());
+        assert!(FlashSale { price_denominator: 0, ..sale.clone() }.check_terms().is_err());
+        assert!(FlashSale { commit_mode: true, soft_cap: 1, ..sale.clone() }.check_terms().is_err());
+
+        let soft_cap_sale = FlashSale {
+            soft_cap: 1_000,
+            end_timestamp: sale.init_timestamp + MAX_SOFT_CAP_SALE_DURATION,
+            ..sale
+        };
+        assert!(soft_cap_sale.check_terms().is_ok());
+        let extended = FlashSale {
+            end_timestamp: soft_cap_sale.end_timestamp + 1,
+            ..soft_cap_sale
+        };
+        assert!(extended.check_terms().is_err());
+    }
+
+    #[test]
+    fn truncated_init_args_are_an_error() {
+        let data = init_args("Ticket");
+        for len in [0, 20, 30, data.len() - 1] {
+            assert!(InitFlashSaleArgs::try_from(&data[..len]).is_err());
+        }
+    }
+}
@@ -1,10 +1,35 @@
+pub mod buyer_record;
+pub mod claim;
+pub mod claim_referral;
 pub mod close_sale;
+pub mod commit;
+pub mod deposit_more;
+pub mod error;
+pub mod events;
 pub mod get_token;
+#[cfg(feature = "indexer")]
+pub mod indexer;
+pub mod init_config;
 pub mod init_flash_sale;
+pub mod pause_sale;
+pub mod proceeds_vault;
+pub mod quote;
+pub mod referral_record;
+pub mod refund;
+pub mod registry;
+pub mod revenue_split;
+pub mod sale_counter;
+pub mod seeds;
+pub mod transfer_authority;
+pub mod update_config;
+pub mod update_metadata;
+pub mod update_sale;
 pub mod utils;
+pub mod withdraw_proceeds;
+pub mod withdraw_unsold;
 
 use pinocchio::{
-    account_info::AccountInfo, default_allocator, default_panic_handler, entrypoint, msg,
+    account_info::AccountInfo, default_allocator,
     program_entrypoint, program_error::ProgramError, pubkey::Pubkey, ProgramResult,
     MAX_TX_ACCOUNTS,
 };
@@ -12,7 +37,11 @@ use pinocchio::{
 use pinocchio_pubkey::declare_id;
 use shank::ShankInstruction;
 
-use crate::{get_token::GetTokenArgs, init_flash_sale::InitFlashSaleArgs};
+use crate::{
+    commit::CommitArgs, get_token::{GetTokenArgs, GetTokenWithLimitsArgs}, init_config::InitConfigArgs, init_flash_sale::InitFlashSaleArgs,
+    quote::QuoteArgs, transfer_authority::ProposeAuthorityArgs, update_config::UpdateConfigArgs, update_metadata::SaleMetadata,
+    update_sale::UpdateSaleArgs,
+};
 
 declare_id!("96Dq3cwtPC7G8genqLeLKcwVHtxvCxwEFbGLRgLnNZQ8");
 
@@ -22,50 +51,263 @@ default_allocator!();
 #[derive(Debug, Clone, ShankInstruction)]
 #[rustfmt::skip]
 pub enum CloseSale {
+  /// Mints with a transfer hook: append every extra account the hook lists, its
+  /// extra-account-metas validation account and the hook program after the
+  /// accounts below.
+  /// sale_seed is the item name, its sha256 hash or empty, depending on
+  /// seed_scheme; see `seeds::sale_seed`. sale_id (u64 LE) must equal the
+  /// `next_sale_id` of the owner's sale counter, which is 0 before its first
+  /// sale. Sales created before the layout was versioned have no sale_id seed.
   #[account(0, writable, signer, name="owner", desc="Owner of the flash sale")]
   #[account(1, writable, name="token_mint", desc="Token mint address of the item being sold")]
   #[account(2, writable, name="source_token_account", desc="Token account with tokens to supply for the sale")]
-  #[account(3, writable, name="token_deposit_pda", desc="Account to hold tokens for the sale. Seeds = [\"deposit\", \"item_name\", token_mint, owner]")]
+  #[account(3, writable, name="token_deposit_pda", desc="Account to hold tokens for the sale. Seeds = [\"deposit\", sale_seed, token_mint, owner, sale_id]")]
   #[account(4, writable, name="token_deposit_ata", desc="Associated token account for token_deposit_pda.")]
-  #[account(5, writable, name="flash_sale_pda", desc="Account to hold flash sale config and state. Seeds = [\"sale\", \"item_name\", token_mint, owner]")]
+  #[account(5, writable, name="flash_sale_pda", desc="Account to hold flash sale config and state. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
   #[account(6, name="system_program", desc = "System program.")]
   #[account(7, name="token_program", desc = "Token program")]
   #[account(8, name="associated_token_program", desc = "Assosiated token program")]
   #[account(9, name="Sysvar Clock", desc = "Sysvar Clock")]
   #[account(10, name="Sysvar Rent", desc = "Sysvar Rent")]
+  #[account(11, writable, name="proceeds_vault_pda", desc="Account that collects the sale proceeds. Seeds = [\"proceeds\", flash_sale_pda]")]
+  #[account(12, optional, name="payment_mint", desc="Payment mint, required when the sale is priced in an SPL token. Mints with a transfer fee are rejected")]
+  #[account(13, optional, writable, name="proceeds_vault_ata", desc="Associated token account of proceeds_vault_pda for payment_mint")]
+  #[account(14, optional, name="payment_token_program", desc="Token program of payment_mint")]
+  #[account(15, writable, name="registry_pda", desc="Sales of the owner. Seeds = [\"registry\", owner]")]
+  #[account(16, writable, name="registry_page_pda", desc="Registry page for the owner's next sale, index total / 32. Seeds = [\"registry\", owner, page as u32 LE]")]
+  #[account(17, optional, writable, name="global_registry_pda", desc="Registry of all sales. Pass the program id to stay unlisted. Seeds = [\"registry\", [0; 32]]")]
+  #[account(18, optional, writable, name="global_registry_page_pda", desc="Global registry page for the next sale. Seeds = [\"registry\", [0; 32], page as u32 LE]")]
+  #[account(19, writable, name="sale_counter_pda", desc="Hands out the owner's sale ids. Seeds = [\"counter\", owner]")]
   InitInstruction(InitFlashSaleArgs),
   
-  #[account(0, writable, signer, name="owner", desc="Owner of the flash sale")]
-  #[account(1, writable, name="receiver_token_ata", desc="Token account that will receivet the tokens")]
+  /// Mints with a transfer hook: append every extra account the hook lists, its
+  /// extra-account-metas validation account and the hook program after the
+  /// accounts below.
+  #[account(0, writable, signer, name="authority", desc="Authority of the flash sale")]
+  #[account(1, writable, name="receiver_token_ata", desc="Token account that will receive the remaining tokens")]
   #[account(2, writable, name="token_mint", desc="Token mint address of the item to get")]
-  #[account(3, writable, name="token_deposit_pda", desc="Account to hold tokens for the sale. Seeds = [\"deposit\", \"item_name\", token_mint, owner]")]
+  #[account(3, writable, name="token_deposit_pda", desc="Account to hold tokens for the sale. Seeds = [\"deposit\", sale_seed, token_mint, owner, sale_id]")]
   #[account(4, writable, name="token_deposit_ata", desc="Associated token account for token_deposit_pda.")]
-  #[account(5, writable, name="flash_sale_pda", desc="Account to hold flash sale config and state. Seeds = [\"sale\", \"item_name\", token_mint, owner]")]
+  #[account(5, writable, name="flash_sale_pda", desc="Account to hold flash sale config and state. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
   #[account(6, name="system_program", desc = "System program.")]
   #[account(7, name="token_program", desc = "Token program")]
   #[account(8, name="associated_token_program", desc = "Assosiated token program")]
   #[account(9, name="Sysvar Clock", desc = "Sysvar Clock")]
   #[account(10, name="Sysvar Rent", desc = "Sysvar Rent")]
-  CloseSale(GetTokenArgs),
+  #[account(11, writable, name="proceeds_vault_pda", desc="Account that collects the sale proceeds. Seeds = [\"proceeds\", flash_sale_pda]")]
+  #[account(12, optional, name="payment_mint", desc="Payment mint, required when the sale is priced in an SPL token")]
+  #[account(13, optional, writable, name="proceeds_vault_ata", desc="Associated token account of proceeds_vault_pda for payment_mint")]
+  #[account(14, optional, name="payment_token_program", desc="Token program of payment_mint")]
+  #[account(15, writable, name="registry_pda", desc="Sales of the owner. Pass the program id for sales created before the layout was versioned. Seeds = [\"registry\", owner]")]
+  #[account(16, writable, name="registry_page_pda", desc="Registry page holding the sale, index registry_index / 32. Pass the program id for sales created before the layout was versioned. Seeds = [\"registry\", owner, page as u32 LE]")]
+  #[account(17, optional, writable, name="global_registry_pda", desc="Registry of all sales. Pass the program id when the sale is unlisted. Seeds = [\"registry\", [0; 32]]")]
+  #[account(18, optional, writable, name="global_registry_page_pda", desc="Global registry page holding the sale. Seeds = [\"registry\", [0; 32], page as u32 LE]")]
+  CloseSale,
 
+  /// Mints with a transfer hook: append every extra account the hook lists, its
+  /// extra-account-metas validation account and the hook program after the
+  /// accounts below.
   #[account(0, writable, signer, name="payer", desc="Payer of the Sol")]
-  #[account(1, writable, name="receiver_token_ata", desc="Token account that will receivet the tokens")]
+  #[account(1, writable, name="receiver_token_ata", desc="Associated token account of recipient for token_mint. Created if it does not exist; unused when the sale vests")]
   #[account(2, writable, name="token_mint", desc="Token mint address of the item to get")]
-  #[account(3, writable, name="token_deposit_pda", desc="Account that holds tokens for the sale. Seeds = [\"deposit\", \"item_name\", token_mint, owner]")]
+  #[account(3, writable, name="token_deposit_pda", desc="Account that holds tokens for the sale. Seeds = [\"deposit\", sale_seed, token_mint, owner, sale_id]")]
   #[account(4, writable, name="token_deposit_ata", desc="Associated token account for token_deposit_pda.")]
-  #[account(5, writable, name="flash_sale_owner", desc="Owner Pubkey of the flash sale owner.")]
-  #[account(6, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", \"item_name\", token_mint, owner]")]
+  #[account(5, writable, name="proceeds_vault_pda", desc="Account that collects the sale proceeds. Seeds = [\"proceeds\", flash_sale_pda]")]
+  #[account(6, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
   #[account(7, name="system_program", desc = "System program.")]
   #[account(8, name="token_program", desc = "Token program")]
   #[account(9, name="associated_token_program", desc = "Assosiated token program")]
   #[account(10, name="Sysvar Clock", desc = "Sysvar Clock")]
   #[account(11, name="Sysvar Rent", desc = "Sysvar Rent")]
-
+  #[account(12, optional, writable, name="buyer_record_pda", desc="Purchases made for the recipient, required when the sale has a per-wallet limit, a soft cap or vesting. Seeds = [\"buyer\", flash_sale_pda, recipient]")]
+  #[account(13, optional, writable, name="payer_payment_account", desc="Payer token account for payment_mint, required when the sale is priced in an SPL token")]
+  #[account(14, optional, name="payment_mint", desc="Payment mint, required when the sale is priced in an SPL token")]
+  #[account(15, optional, writable, name="proceeds_vault_ata", desc="Associated token account of proceeds_vault_pda for payment_mint")]
+  #[account(16, optional, name="payment_token_program", desc="Token program of payment_mint")]
+  #[account(17, name="config_pda", desc="Global program config. Seeds = [\"config\"]")]
+  #[account(18, writable, name="fee_recipient", desc="Protocol fee recipient. A token account for payment_mint when the sale is priced in an SPL token")]
+  #[account(19, optional, name="referrer", desc="Wallet that referred the buyer, other than the payer and the recipient. Pass the program id when there is no referrer")]
+  #[account(20, optional, writable, name="referral_record_pda", desc="Referral volume and earnings, required with referrer. Seeds = [\"referral\", flash_sale_pda, referrer]")]
+  #[account(21, optional, name="recipient", desc="Wallet receiving the tokens. Defaults to the payer; pass the program id to keep the default. Refunds go to the payer of its first purchase, so on soft cap sales only that payer can buy for it")]
   GetToken(GetTokenArgs),
+
+  /// Mints with a transfer hook: append every extra account the hook lists, its
+  /// extra-account-metas validation account and the hook program after the
+  /// accounts below.
+  #[account(0, writable, signer, name="authority", desc="Authority of the flash sale")]
+  #[account(1, name="token_mint", desc="Token mint address of the item being sold")]
+  #[account(2, writable, name="source_token_account", desc="Token account with tokens to add to the sale")]
+  #[account(3, writable, name="token_deposit_ata", desc="Associated token account for token_deposit_pda.")]
+  #[account(4, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(5, name="token_program", desc = "Token program")]
+  DepositMore(GetTokenArgs),
+
+  /// Mints with a transfer hook: append every extra account the hook lists, its
+  /// extra-account-metas validation account and the hook program after the
+  /// accounts below.
+  #[account(0, writable, signer, name="authority", desc="Authority of the flash sale")]
+  #[account(1, writable, name="receiver_token_ata", desc="Token account that will receive the unsold tokens")]
+  #[account(2, name="token_mint", desc="Token mint address of the item being sold")]
+  #[account(3, name="token_deposit_pda", desc="Account that holds tokens for the sale. Seeds = [\"deposit\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(4, writable, name="token_deposit_ata", desc="Associated token account for token_deposit_pda.")]
+  #[account(5, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(6, name="token_program", desc = "Token program")]
+  WithdrawUnsold(GetTokenArgs),
+
+  #[account(0, signer, name="authority", desc="Authority of the flash sale")]
+  #[account(1, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  UpdateSale(UpdateSaleArgs),
+
+  #[account(0, signer, name="authority", desc="Authority or operator of the flash sale")]
+  #[account(1, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  PauseSale,
+
+  #[account(0, signer, name="authority", desc="Authority or operator of the flash sale")]
+  #[account(1, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  ResumeSale,
+
+  #[account(0, signer, name="authority", desc="Current authority of the flash sale")]
+  #[account(1, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  ProposeAuthority(ProposeAuthorityArgs),
+
+  #[account(0, signer, name="new_authority", desc="Proposed authority of the flash sale")]
+  #[account(1, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  AcceptAuthority,
+
+  #[account(0, signer, name="authority", desc="Authority of the flash sale")]
+  #[account(1, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(2, writable, name="proceeds_vault_pda", desc="Account that collects the sale proceeds. Seeds = [\"proceeds\", flash_sale_pda]")]
+  #[account(3, writable, name="receiver", desc="Account that receives the proceeds. A token account for payment_mint when the sale is priced in an SPL token. Ignored when the sale has payees, whose accounts follow fee_recipient in payee order")]
+  #[account(4, optional, name="payment_mint", desc="Payment mint, required when the sale is priced in an SPL token")]
+  #[account(5, optional, writable, name="proceeds_vault_ata", desc="Associated token account of proceeds_vault_pda for payment_mint")]
+  #[account(6, optional, name="payment_token_program", desc="Token program of payment_mint")]
+  #[account(7, name="config_pda", desc="Global program config, required when the sale has a soft cap. Pass the program id otherwise. Seeds = [\"config\"]")]
+  #[account(8, writable, name="fee_recipient", desc="Protocol fee recipient for the fees held until the soft cap was reached. A token account for payment_mint when the sale is priced in an SPL token. Pass the program id when the sale has no soft cap")]
+  WithdrawProceeds,
+
+  /// Returns everything paid for the buyer, including the protocol fees and
+  /// referral rewards held in the vault for the sale, to the wallet that paid.
+  /// Mints with a transfer hook: append every extra account the hook lists, its
+  /// extra-account-metas validation account and the hook program after the
+  /// accounts below.
+  #[account(0, writable, signer, name="buyer", desc="Buyer asking for a refund")]
+  #[account(1, writable, name="buyer_record_pda", desc="Purchases made by the buyer. Seeds = [\"buyer\", flash_sale_pda, buyer]")]
+  #[account(2, writable, name="buyer_token_account", desc="Token account holding the purchased tokens to return")]
+  #[account(3, name="token_mint", desc="Token mint address of the item being sold")]
+  #[account(4, writable, name="token_deposit_ata", desc="Associated token account for token_deposit_pda.")]
+  #[account(5, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(6, writable, name="proceeds_vault_pda", desc="Account that collects the sale proceeds. Seeds = [\"proceeds\", flash_sale_pda]")]
+  #[account(7, name="token_program", desc = "Token program")]
+  #[account(8, writable, name="payer_refund_account", desc="Wallet that paid for the buyer's purchases, as stored in buyer_record_pda. Its token account for payment_mint when the sale is priced in an SPL token")]
+  #[account(9, optional, name="payment_mint", desc="Payment mint, required when the sale is priced in an SPL token")]
+  #[account(10, optional, writable, name="proceeds_vault_ata", desc="Associated token account of proceeds_vault_pda for payment_mint")]
+  #[account(11, optional, name="payment_token_program", desc="Token program of payment_mint")]
+  Refund,
+
+  #[account(0, writable, signer, name="payer", desc="Upgrade authority of the program")]
+  #[account(1, writable, name="config_pda", desc="Global program config. Seeds = [\"config\"]")]
+  #[account(2, name="program_data", desc="Program data account of this program")]
+  #[account(3, name="system_program", desc = "System program.")]
+  InitConfig(InitConfigArgs),
+
+  #[account(0, signer, name="admin", desc="Admin of the global config")]
+  #[account(1, writable, name="config_pda", desc="Global program config. Seeds = [\"config\"]")]
+  UpdateConfig(UpdateConfigArgs),
+
+  /// Rewards unlock together with the sale proceeds; see WithdrawProceeds.
+  #[account(0, signer, name="referrer", desc="Referrer claiming its rewards")]
+  #[account(1, writable, name="referral_record_pda", desc="Referral volume and earnings. Seeds = [\"referral\", flash_sale_pda, referrer]")]
+  #[account(2, writable, name="flash_sale_pda", desc="Account to hold flash sale config and state. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(3, writable, name="proceeds_vault_pda", desc="Account that collects the sale proceeds. Seeds = [\"proceeds\", flash_sale_pda]")]
+  #[account(4, writable, name="receiver", desc="Wallet (SOL sales) or payment token account receiving the rewards")]
+  #[account(5, optional, name="payment_mint", desc="Payment mint, required when the sale is priced in an SPL token")]
+  #[account(6, optional, writable, name="proceeds_vault_ata", desc="Associated token account of proceeds_vault_pda for payment_mint")]
+  #[account(7, optional, name="payment_token_program", desc="Token program of payment_mint")]
+  ClaimReferral,
+
+  /// Mints with a transfer hook: append every extra account the hook lists, its
+  /// extra-account-metas validation account and the hook program after the
+  /// accounts below.
+  #[account(0, writable, signer, name="payer", desc="Payer of the Sol")]
+  #[account(1, writable, name="receiver_token_ata", desc="Associated token account of recipient for token_mint. Created if it does not exist; unused when the sale vests")]
+  #[account(2, writable, name="token_mint", desc="Token mint address of the item to get")]
+  #[account(3, writable, name="token_deposit_pda", desc="Account that holds tokens for the sale. Seeds = [\"deposit\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(4, writable, name="token_deposit_ata", desc="Associated token account for token_deposit_pda.")]
+  #[account(5, writable, name="proceeds_vault_pda", desc="Account that collects the sale proceeds. Seeds = [\"proceeds\", flash_sale_pda]")]
+  #[account(6, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(7, name="system_program", desc = "System program.")]
+  #[account(8, name="token_program", desc = "Token program")]
+  #[account(9, name="associated_token_program", desc = "Assosiated token program")]
+  #[account(10, name="Sysvar Clock", desc = "Sysvar Clock")]
+  #[account(11, name="Sysvar Rent", desc = "Sysvar Rent")]
+  #[account(12, optional, writable, name="buyer_record_pda", desc="Purchases made for the recipient, required when the sale has a per-wallet limit, a soft cap or vesting. Seeds = [\"buyer\", flash_sale_pda, recipient]")]
+  #[account(13, optional, writable, name="payer_payment_account", desc="Payer token account for payment_mint, required when the sale is priced in an SPL token")]
+  #[account(14, optional, name="payment_mint", desc="Payment mint, required when the sale is priced in an SPL token")]
+  #[account(15, optional, writable, name="proceeds_vault_ata", desc="Associated token account of proceeds_vault_pda for payment_mint")]
+  #[account(16, optional, name="payment_token_program", desc="Token program of payment_mint")]
+  #[account(17, name="config_pda", desc="Global program config. Seeds = [\"config\"]")]
+  #[account(18, writable, name="fee_recipient", desc="Protocol fee recipient. A token account for payment_mint when the sale is priced in an SPL token")]
+  #[account(19, optional, name="referrer", desc="Wallet that referred the buyer, other than the payer and the recipient. Pass the program id when there is no referrer")]
+  #[account(20, optional, writable, name="referral_record_pda", desc="Referral volume and earnings, required with referrer. Seeds = [\"referral\", flash_sale_pda, referrer]")]
+  #[account(21, optional, name="recipient", desc="Wallet receiving the tokens. Defaults to the payer; pass the program id to keep the default. Refunds go to the payer of its first purchase, so on soft cap sales only that payer can buy for it")]
+  GetTokenWithLimits(GetTokenWithLimitsArgs),
+
+  /// Read-only: simulate it to price a purchase. Returns a QuoteResult through
+  /// the transaction return data.
+  #[account(0, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(1, name="token_mint", desc="Token mint address of the item to quote")]
+  #[account(2, name="config_pda", desc="Global program config. Seeds = [\"config\"]")]
+  #[account(3, optional, name="buyer", desc="Wallet that would receive the tokens, used for the per-wallet limit. Pass the program id when unknown")]
+  #[account(4, optional, name="buyer_record_pda", desc="Purchases made for buyer. Seeds = [\"buyer\", flash_sale_pda, buyer]")]
+  #[account(5, optional, name="referrer", desc="Wallet that would refer the purchase. Pass the program id when there is no referrer")]
+  Quote(QuoteArgs),
+
+  #[account(0, writable, signer, name="authority", desc="Authority of the flash sale. Pays for or is refunded the rent difference")]
+  #[account(1, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(2, name="system_program", desc = "System program.")]
+  UpdateMetadata(SaleMetadata),
+
+  /// Mints with a transfer hook: append every extra account the hook lists, its
+  /// extra-account-metas validation account and the hook program after the
+  /// accounts below.
+  /// Commit sales: the first claim after the end settles the commitment,
+  /// refunding the payment beyond the buyer's pro rata allocation. Accounts
+  /// 8-14 are only passed for commit sales; transfer hook accounts follow them.
+  /// Anyone can claim on a buyer's behalf, so unclaimed tokens never keep the
+  /// sale from closing; without the buyer's signature the tokens and the refund
+  /// go to the buyer's associated token accounts.
+  #[account(0, writable, optional_signer, name="buyer", desc="Wallet the tokens were bought or committed for. Signs unless the claim is made on its behalf")]
+  #[account(1, writable, name="buyer_record_pda", desc="Purchases made for the buyer. Seeds = [\"buyer\", flash_sale_pda, buyer]")]
+  #[account(2, writable, name="buyer_token_account", desc="Token account of buyer receiving the tokens. Its associated token account when buyer does not sign")]
+  #[account(3, name="token_mint", desc="Token mint address of the item being sold")]
+  #[account(4, name="token_deposit_pda", desc="Account that holds tokens for the sale. Seeds = [\"deposit\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(5, writable, name="token_deposit_ata", desc="Associated token account for token_deposit_pda.")]
+  #[account(6, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(7, name="token_program", desc = "Token program")]
+  #[account(8, optional, writable, name="proceeds_vault_pda", desc="Commit sales: account holding the commitments. Seeds = [\"proceeds\", flash_sale_pda]")]
+  #[account(9, optional, name="config_pda", desc="Commit sales: global program config. Seeds = [\"config\"]")]
+  #[account(10, optional, writable, name="fee_recipient", desc="Commit sales: protocol fee recipient. A token account for payment_mint when the sale is priced in an SPL token")]
+  #[account(11, optional, writable, name="buyer_payment_account", desc="Commit sales: buyer token account for payment_mint receiving the excess payment. Its associated token account when buyer does not sign")]
+  #[account(12, optional, name="payment_mint", desc="Payment mint, required when the sale is priced in an SPL token")]
+  #[account(13, optional, writable, name="proceeds_vault_ata", desc="Associated token account of proceeds_vault_pda for payment_mint")]
+  #[account(14, optional, name="payment_token_program", desc="Token program of payment_mint")]
+  Claim,
+
+  #[account(0, writable, signer, name="payer", desc="Buyer committing payment")]
+  #[account(1, writable, name="flash_sale_pda", desc="Account that holds information about the sale. Seeds = [\"sale\", sale_seed, token_mint, owner, sale_id]")]
+  #[account(2, writable, name="proceeds_vault_pda", desc="Account that collects the commitments. Seeds = [\"proceeds\", flash_sale_pda]")]
+  #[account(3, writable, name="buyer_record_pda", desc="Commitments made by payer. Seeds = [\"buyer\", flash_sale_pda, payer]")]
+  #[account(4, name="system_program", desc = "System program.")]
+  #[account(5, optional, writable, name="payer_payment_account", desc="Payer token account for payment_mint, required when the sale is priced in an SPL token")]
+  #[account(6, optional, name="payment_mint", desc="Payment mint, required when the sale is priced in an SPL token")]
+  #[account(7, optional, writable, name="proceeds_vault_ata", desc="Associated token account of proceeds_vault_pda for payment_mint")]
+  #[account(8, optional, name="payment_token_program", desc="Token program of payment_mint")]
+  #[account(9, name="config_pda", desc="Global program config. Seeds = [\"config\"]")]
+  Commit(CommitArgs),
 }
 
 pub fn process_instruction(
-    program_id: &Pubkey,
+    _program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
@@ -73,6 +315,23 @@ pub fn process_instruction(
         0 => init_flash_sale::init_flash_sale(accounts, &instruction_data[1..]),
         1 => close_sale::close_sale(accounts, &instruction_data[1..]),
         2 => get_token::get_token(accounts, &instruction_data[1..]),
+        3 => deposit_more::deposit_more(accounts, &instruction_data[1..]),
+        4 => withdraw_unsold::withdraw_unsold(accounts, &instruction_data[1..]),
+        5 => update_sale::update_sale(accounts, &instruction_data[1..]),
+        6 => pause_sale::pause_sale(accounts, &instruction_data[1..]),
+        7 => pause_sale::resume_sale(accounts, &instruction_data[1..]),
+        8 => transfer_authority::propose_authority(accounts, &instruction_data[1..]),
+        9 => transfer_authority::accept_authority(accounts, &instruction_data[1..]),
+        10 => withdraw_proceeds::withdraw_proceeds(accounts, &instruction_data[1..]),
+        11 => refund::refund(accounts, &instruction_data[1..]),
+        12 => init_config::init_config(accounts, &instruction_data[1..]),
+        13 => update_config::update_config(accounts, &instruction_data[1..]),
+        14 => claim_referral::claim_referral(accounts, &instruction_data[1..]),
+        15 => get_token::get_token_with_limits(accounts, &instruction_data[1..]),
+        16 => quote::quote(accounts, &instruction_data[1..]),
+        17 => update_metadata::update_metadata(accounts, &instruction_data[1..]),
+        18 => claim::claim(accounts, &instruction_data[1..]),
+        19 => commit::commit(accounts, &instruction_data[1..]),
         _ => Err(ProgramError::InvalidInstructionData),
     }
 }
@@ -0,0 +1,45 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::{msg, ProgramResult};
+
+use crate::error::FlashSaleError;
+use crate::events::emit_sale_updated;
+use crate::init_flash_sale::FlashSale;
+use crate::utils::check_owner;
+
+fn set_paused(accounts: &[AccountInfo], paused: bool) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let authority = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+
+    if !authority.is_signer(){
+        msg!("Authority or operator must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.authority_address != *authority.key()
+        && flash_sale_settings.operator_address != *authority.key()
+    {
+        msg!("Signer is neither the authority nor the operator");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+
+    flash_sale_settings.paused = paused;
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    emit_sale_updated(flash_sale_pda.key(), &flash_sale_settings)
+}
+
+pub fn pause_sale(accounts: &[AccountInfo], _instruction_data: &[u8]) -> ProgramResult {
+    set_paused(accounts, true)
+}
+
+pub fn resume_sale(accounts: &[AccountInfo], _instruction_data: &[u8]) -> ProgramResult {
+    set_paused(accounts, false)
+}
@@ -0,0 +1,266 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::instruction::Signer;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::sysvars::rent::Rent;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, seeds, ProgramResult};
+use shank::ShankType;
+
+use crate::init_flash_sale::FlashSale;
+use crate::utils::{
+    check_address_is_any, check_owner, check_token_account_owner, close_token_account, find_program_address,
+    get_mint_decimals, get_transfer_fee, transfer_tokens,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ShankType)]
+pub enum ProceedsWithdrawRule {
+    Immediate,
+    AfterSaleEnd,
+    SoftCapReached,
+}
+
+impl TryFrom<u8> for ProceedsWithdrawRule {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ProceedsWithdrawRule::Immediate),
+            1 => Ok(ProceedsWithdrawRule::AfterSaleEnd),
+            2 => Ok(ProceedsWithdrawRule::SoftCapReached),
+            _ => Err("Invalid withdraw rule".to_string()),
+        }
+    }
+}
+
+pub struct PaymentTokenAccounts<'a> {
+    pub payment_mint: &'a AccountInfo,
+    pub vault_token_account: &'a AccountInfo,
+    pub payment_token_program: &'a AccountInfo,
+}
+
+impl<'a> PaymentTokenAccounts<'a> {
+    pub fn from_iter<I: Iterator<Item = &'a AccountInfo>>(
+        flash_sale_settings: &FlashSale,
+        accounts_iter: &mut I,
+    ) -> Result<Option<Self>, ProgramError> {
+        let payment_mint = accounts_iter.next();
+        let vault_token_account = accounts_iter.next();
+        let payment_token_program = accounts_iter.next();
+
+        if !flash_sale_settings.pays_in_token() {
+            return Ok(None);
+        }
+
+        let payment_mint = payment_mint.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let vault_token_account = vault_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let payment_token_program = payment_token_program.ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        if flash_sale_settings.payment_mint != *payment_mint.key(){
+            msg!("Unexpected payment mint address");
+            return Err(ProgramError::InvalidArgument);
+        }
+        check_address_is_any(payment_token_program, &[pinocchio_token::id(), spl_token_2022::id().to_bytes()])?;
+
+        Ok(Some(PaymentTokenAccounts {
+            payment_mint,
+            vault_token_account,
+            payment_token_program,
+        }))
+    }
+}
+
+pub fn find_proceeds_vault(flash_sale_pda: &AccountInfo, proceeds_vault_pda: &AccountInfo) -> Result<u8, ProgramError> {
+    let expected_vault_account = find_program_address(
+        &[b"proceeds", flash_sale_pda.key()],
+        &crate::id(),
+    );
+    if expected_vault_account.0 != *proceeds_vault_pda.key() {
+        msg!("Unexpected proceeds vault address");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(expected_vault_account.1)
+}
+
+pub fn create_proceeds_vault(
+    payer: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+    proceeds_vault_pda: &AccountInfo,
+    system_program: &AccountInfo,
+    payment_accounts: Option<&PaymentTokenAccounts>,
+) -> ProgramResult {
+    let vault_bump = find_proceeds_vault(flash_sale_pda, proceeds_vault_pda)?;
+    let vault_binding = [vault_bump];
+    let vault_seeds = seeds!(b"proceeds", flash_sale_pda.key(), &vault_binding);
+
+    if proceeds_vault_pda.owner() != &crate::id() {
+        let rent = Rent::get()?;
+        pinocchio_system::instructions::CreateAccount {
+            from: payer,
+            to: proceeds_vault_pda,
+            space: 0,
+            lamports: rent.minimum_balance(0),
+            owner: &crate::id(),
+        }
+        .invoke_signed(&[Signer::from(&vault_seeds)])?;
+    }
+
+    if let Some(payment_accounts) = payment_accounts {
+        // Proceeds are booked at the amount the buyer pays, so a transfer fee
+        // would leave the vault short. Mint extensions are fixed when the mint
+        // is created, so checking once here is enough.
+        if get_transfer_fee(payment_accounts.payment_mint, 0)?.is_some() {
+            msg!("Payment mints with a transfer fee are not supported");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if *payment_accounts.vault_token_account.try_borrow_lamports()? == 0 {
+            pinocchio_associated_token_account::instructions::Create {
+                funding_account: payer,
+                account: payment_accounts.vault_token_account,
+                wallet: proceeds_vault_pda,
+                mint: payment_accounts.payment_mint,
+                system_program,
+                token_program: payment_accounts.payment_token_program,
+            }
+            .invoke()?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn collect_payment(
+    payer: &AccountInfo,
+    payer_token_account: Option<&AccountInfo>,
+    flash_sale_pda: &AccountInfo,
+    proceeds_vault_pda: &AccountInfo,
+    payment_accounts: Option<&PaymentTokenAccounts>,
+    amount: u64,
+) -> ProgramResult {
+    find_proceeds_vault(flash_sale_pda, proceeds_vault_pda)?;
+
+    match payment_accounts {
+        None => pinocchio_system::instructions::Transfer {
+            from: payer,
+            to: proceeds_vault_pda,
+            lamports: amount,
+        }
+        .invoke(),
+        Some(payment_accounts) => {
+            check_token_account_owner(payment_accounts.vault_token_account, proceeds_vault_pda.key())?;
+            let payer_token_account = payer_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            transfer_tokens(
+                payment_accounts.payment_token_program,
+                payer_token_account,
+                payment_accounts.payment_mint,
+                payment_accounts.vault_token_account,
+                payer,
+                amount,
+                get_mint_decimals(payment_accounts.payment_mint)?,
+                &[],
+                &[],
+            )
+        }
+    }
+}
+
+pub fn collect_fee(
+    payer: &AccountInfo,
+    payer_token_account: Option<&AccountInfo>,
+    fee_recipient: &AccountInfo,
+    expected_fee_recipient: &Pubkey,
+    payment_accounts: Option<&PaymentTokenAccounts>,
+    amount: u64,
+) -> ProgramResult {
+    match payment_accounts {
+        None => {
+            if fee_recipient.key() != expected_fee_recipient {
+                msg!("Unexpected fee recipient");
+                return Err(ProgramError::InvalidArgument);
+            }
+            pinocchio_system::instructions::Transfer {
+                from: payer,
+                to: fee_recipient,
+                lamports: amount,
+            }
+            .invoke()
+        }
+        Some(payment_accounts) => {
+            check_token_account_owner(fee_recipient, expected_fee_recipient)?;
+            let payer_token_account = payer_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            transfer_tokens(
+                payment_accounts.payment_token_program,
+                payer_token_account,
+                payment_accounts.payment_mint,
+                fee_recipient,
+                payer,
+                amount,
+                get_mint_decimals(payment_accounts.payment_mint)?,
+                &[],
+                &[],
+            )
+        }
+    }
+}
+
+pub fn pay_out(
+    flash_sale_pda: &AccountInfo,
+    proceeds_vault_pda: &AccountInfo,
+    receiver: &AccountInfo,
+    payment_accounts: Option<&PaymentTokenAccounts>,
+    amount: u64,
+) -> ProgramResult {
+    let vault_bump = find_proceeds_vault(flash_sale_pda, proceeds_vault_pda)?;
+    check_owner(proceeds_vault_pda, crate::id())?;
+
+    match payment_accounts {
+        None => {
+            let mut vault_lamports = proceeds_vault_pda.try_borrow_mut_lamports()?;
+            *vault_lamports = vault_lamports
+                .checked_sub(amount)
+                .ok_or(ProgramError::InsufficientFunds)?;
+            *receiver.try_borrow_mut_lamports()? += amount;
+            Ok(())
+        }
+        Some(payment_accounts) => {
+            check_token_account_owner(payment_accounts.vault_token_account, proceeds_vault_pda.key())?;
+            let vault_binding = [vault_bump];
+            let vault_seeds = seeds!(b"proceeds", flash_sale_pda.key(), &vault_binding);
+            transfer_tokens(
+                payment_accounts.payment_token_program,
+                payment_accounts.vault_token_account,
+                payment_accounts.payment_mint,
+                receiver,
+                proceeds_vault_pda,
+                amount,
+                get_mint_decimals(payment_accounts.payment_mint)?,
+                &[],
+                &[Signer::from(&vault_seeds)],
+            )
+        }
+    }
+}
+
+pub fn close_proceeds_vault(
+    flash_sale_pda: &AccountInfo,
+    proceeds_vault_pda: &AccountInfo,
+    receiver: &AccountInfo,
+    payment_accounts: Option<&PaymentTokenAccounts>,
+) -> ProgramResult {
+    let vault_bump = find_proceeds_vault(flash_sale_pda, proceeds_vault_pda)?;
+
+    if let Some(payment_accounts) = payment_accounts {
+        check_token_account_owner(payment_accounts.vault_token_account, proceeds_vault_pda.key())?;
+        let vault_binding = [vault_bump];
+        let vault_seeds = seeds!(b"proceeds", flash_sale_pda.key(), &vault_binding);
+        close_token_account(
+            payment_accounts.payment_token_program,
+            payment_accounts.vault_token_account,
+            receiver,
+            proceeds_vault_pda,
+            &[Signer::from(&vault_seeds)],
+        )?;
+    }
+
+    Ok(())
+}
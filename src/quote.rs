@@ -0,0 +1,168 @@
+use pinocchio::{account_info::AccountInfo, cpi::set_return_data, msg, program_error::ProgramError, ProgramResult};
+use pinocchio::sysvars::{clock::Clock, Sysvar};
+use shank::ShankType;
+
+use crate::buyer_record::{find_buyer_record, load_buyer_record};
+use crate::init_config::load_config;
+use crate::init_flash_sale::{FlashSale, PurchaseCheck};
+use crate::utils::{check_owner, get_transfer_fee};
+
+#[derive(Debug, Clone, ShankType)]
+pub struct QuoteArgs {
+    pub amount: u64,
+}
+
+impl TryFrom<&[u8]> for QuoteArgs {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 8 {
+            return Err("Unexpected end of data".to_string());
+        }
+        let amount = u64::from_le_bytes(data[0..8].try_into().unwrap());
+
+        Ok(QuoteArgs {
+            amount
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ShankType)]
+pub enum SalePhase {
+    Active = 0,
+    Paused = 1,
+    SoldOut = 2,
+    Ended = 3,
+}
+
+impl SalePhase {
+    pub fn of(sale: &FlashSale, now: i64) -> Self {
+        if !sale.is_active(now) {
+            SalePhase::Ended
+        } else if sale.total_sold >= sale.total_supply {
+            SalePhase::SoldOut
+        } else if sale.paused {
+            SalePhase::Paused
+        } else {
+            SalePhase::Active
+        }
+    }
+}
+
+#[derive(Debug, Clone, ShankType)]
+pub struct QuoteResult {
+    pub price: u64,
+    pub price_denominator: u64,
+    pub total_cost: u64,
+    pub protocol_fee: u64,
+    pub referral_fee: u64,
+    pub transfer_fee: u64,
+    pub net_amount: u64,
+    pub remaining_supply: u64,
+    pub phase: SalePhase,
+    pub can_buy: bool,
+}
+
+pub const QUOTE_RESULT_SIZE: usize = 8 * 8 + 1 + 1;
+
+impl QuoteResult {
+    pub fn write_to_slice(&self, buf: &mut [u8]) -> Result<(), ProgramError> {
+        buf[0..8].copy_from_slice(&self.price.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.price_denominator.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.total_cost.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.protocol_fee.to_le_bytes());
+        buf[32..40].copy_from_slice(&self.referral_fee.to_le_bytes());
+        buf[40..48].copy_from_slice(&self.transfer_fee.to_le_bytes());
+        buf[48..56].copy_from_slice(&self.net_amount.to_le_bytes());
+        buf[56..64].copy_from_slice(&self.remaining_supply.to_le_bytes());
+        buf[64] = self.phase as u8;
+        buf[65] = self.can_buy as u8;
+
+        Ok(())
+    }
+}
+
+// Prices a purchase of `amount` exactly like get_token would, without moving
+// any funds. Meant to be simulated; the answer is in the return data.
+pub fn quote(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let flash_sale_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let token_mint = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let config_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    // Optional accounts take the program id as a placeholder, like get_token.
+    let buyer = accounts_iter.next().filter(|account| *account.key() != crate::id());
+    let buyer_record_pda = accounts_iter.next().filter(|account| *account.key() != crate::id());
+    let referrer = accounts_iter.next().filter(|account| *account.key() != crate::id());
+
+    let args = QuoteArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    check_owner(flash_sale_pda, crate::id())?;
+    let flash_sale_settings = FlashSale::try_from(flash_sale_pda.try_borrow_data()?.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.mint_address != *token_mint.key(){
+        msg!("Unexpected token mint address");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let config = load_config(config_pda)?;
+    let clock = Clock::get()?;
+
+    let total_cost = flash_sale_settings.cost_for(args.amount)?;
+    let protocol_fee = config.as_ref().map_or(0, |config| config.fee_for(total_cost));
+    let referral_fee = match referrer {
+        Some(_) => flash_sale_settings.referral_fee_for(total_cost - protocol_fee),
+        None => 0,
+    };
+    // Matches get_token: vested purchases pay the transfer fee on claim.
+    let transfer_fee = match flash_sale_settings.vests() {
+        true => 0,
+        false => get_transfer_fee(token_mint, args.amount)?.unwrap_or(0),
+    };
+    let net_amount = args.amount - transfer_fee;
+    let remaining_supply = flash_sale_settings.remaining_supply();
+    let phase = SalePhase::of(&flash_sale_settings, clock.unix_timestamp);
+
+    // Without a buyer the per-wallet limit is checked against an empty record.
+    let purchased = match (buyer, buyer_record_pda) {
+        (Some(buyer), Some(buyer_record_pda)) => {
+            find_buyer_record(buyer.key(), buyer_record_pda, flash_sale_pda)?;
+            match buyer_record_pda.owner() == &crate::id() {
+                true => load_buyer_record(buyer.key(), buyer_record_pda, flash_sale_pda)?.purchased,
+                false => 0,
+            }
+        }
+        _ => 0,
+    };
+    // Without a buyer the self-referral check has nothing to compare against.
+    let can_buy = flash_sale_settings
+        .check_purchase(&PurchaseCheck {
+            now: clock.unix_timestamp,
+            amount: args.amount,
+            purchased,
+            buyer: buyer.map_or(&crate::id(), |buyer| buyer.key()),
+            payer: buyer.map_or(&crate::id(), |buyer| buyer.key()),
+            referrer: referrer.map(|referrer| referrer.key()),
+            protocol_paused: config.as_ref().is_some_and(|config| config.paused),
+        })
+        .is_ok();
+
+    let mut result = [0u8; QUOTE_RESULT_SIZE];
+    QuoteResult {
+        price: flash_sale_settings.price,
+        price_denominator: flash_sale_settings.price_denominator,
+        total_cost,
+        protocol_fee,
+        referral_fee,
+        transfer_fee,
+        net_amount,
+        remaining_supply,
+        phase,
+        can_buy,
+    }
+    .write_to_slice(&mut result)?;
+    set_return_data(&result);
+
+    Ok(())
+}
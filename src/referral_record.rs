@@ -0,0 +1,144 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::instruction::Signer;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::sysvars::rent::Rent;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, seeds, ProgramResult};
+use shank::ShankAccount;
+
+use crate::utils::{check_owner, find_program_address};
+
+#[derive(Clone, ShankAccount)]
+pub struct ReferralRecord {
+    pub sale_address: Pubkey,
+    pub referrer_address: Pubkey,
+    pub volume: u64,
+    pub earned: u64,
+    pub claimed: u64,
+}
+
+pub const REFERRAL_RECORD_ACCOUNT_SIZE: usize = 32 + 32 + 8 + 8 + 8;
+
+impl TryFrom<&[u8]> for ReferralRecord {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < REFERRAL_RECORD_ACCOUNT_SIZE {
+            return Err("Referral record too small".to_string());
+        }
+        let sale_address: Pubkey = data[0..32].try_into().unwrap();
+        let referrer_address: Pubkey = data[32..64].try_into().unwrap();
+        let volume = u64::from_le_bytes(data[64..72].try_into().unwrap());
+        let earned = u64::from_le_bytes(data[72..80].try_into().unwrap());
+        let claimed = u64::from_le_bytes(data[80..88].try_into().unwrap());
+
+        Ok(ReferralRecord {
+            sale_address,
+            referrer_address,
+            volume,
+            earned,
+            claimed,
+        })
+    }
+}
+
+impl ReferralRecord {
+    pub fn write_to_slice(&self, buf: &mut [u8]) -> Result<(), ProgramError> {
+        buf[0..32].copy_from_slice(&self.sale_address);
+        buf[32..64].copy_from_slice(&self.referrer_address);
+        buf[64..72].copy_from_slice(&self.volume.to_le_bytes());
+        buf[72..80].copy_from_slice(&self.earned.to_le_bytes());
+        buf[80..88].copy_from_slice(&self.claimed.to_le_bytes());
+
+        Ok(())
+    }
+}
+
+pub fn find_referral_record(
+    referrer: &Pubkey,
+    referral_record_pda: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let expected_record_account = find_program_address(
+        &[b"referral", flash_sale_pda.key(), referrer],
+        &crate::id(),
+    );
+    if expected_record_account.0 != *referral_record_pda.key() {
+        msg!("Unexpected referral record address");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(expected_record_account.1)
+}
+
+pub fn load_referral_record(
+    referrer: &Pubkey,
+    referral_record_pda: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+) -> Result<ReferralRecord, ProgramError> {
+    find_referral_record(referrer, referral_record_pda, flash_sale_pda)?;
+    check_owner(referral_record_pda, crate::id())?;
+
+    let data = referral_record_pda.try_borrow_data()?;
+    ReferralRecord::try_from(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+pub fn load_or_create_referral_record(
+    payer: &AccountInfo,
+    referrer: &Pubkey,
+    referral_record_pda: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+) -> Result<ReferralRecord, ProgramError> {
+    if referral_record_pda.owner() == &crate::id() {
+        return load_referral_record(referrer, referral_record_pda, flash_sale_pda);
+    }
+
+    let record_bump = find_referral_record(referrer, referral_record_pda, flash_sale_pda)?;
+    let record_binding = [record_bump];
+    let record_seeds = seeds!(
+        b"referral",
+        flash_sale_pda.key(),
+        referrer,
+        &record_binding
+    );
+
+    let rent = Rent::get()?;
+    pinocchio_system::instructions::CreateAccount {
+        from: payer,
+        to: referral_record_pda,
+        space: REFERRAL_RECORD_ACCOUNT_SIZE as u64,
+        lamports: rent.minimum_balance(REFERRAL_RECORD_ACCOUNT_SIZE),
+        owner: &crate::id(),
+    }
+    .invoke_signed(&[Signer::from(&record_seeds)])?;
+
+    Ok(ReferralRecord {
+        sale_address: *flash_sale_pda.key(),
+        referrer_address: *referrer,
+        volume: 0,
+        earned: 0,
+        claimed: 0,
+    })
+}
+
+// The referral cut stays in the proceeds vault next to the seller's share; the
+// record only tracks how much of it belongs to the referrer.
+pub fn record_referral(
+    payer: &AccountInfo,
+    referrer: &AccountInfo,
+    referral_record_pda: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+    volume: u64,
+    earned: u64,
+) -> ProgramResult {
+    let mut referral_record = load_or_create_referral_record(payer, referrer.key(), referral_record_pda, flash_sale_pda)?;
+    referral_record.volume = referral_record
+        .volume
+        .checked_add(volume)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    referral_record.earned = referral_record
+        .earned
+        .checked_add(earned)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    referral_record.write_to_slice(&mut referral_record_pda.try_borrow_mut_data()?)
+}
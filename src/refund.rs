@@ -0,0 +1,294 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::sysvars::clock::Clock;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, ProgramResult};
+
+use crate::buyer_record::load_buyer_record;
+use crate::error::FlashSaleError;
+use crate::events::{emit_withdrawal, WithdrawalKind};
+use crate::init_flash_sale::FlashSale;
+use crate::proceeds_vault::{pay_out, PaymentTokenAccounts};
+use crate::utils::{
+    check_address, check_address_is_any, check_owner, check_token_account_owner, deinit_account_if_exists,
+    find_program_address, get_mint_decimals, get_transfer_fee, transfer_tokens,
+};
+
+pub fn refund(accounts: &[AccountInfo], _instruction_data: &[u8]) -> ProgramResult {
+    refund_at(accounts, Clock::get()?.unix_timestamp)
+}
+
+fn refund_at(accounts: &[AccountInfo], now: i64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let buyer = accounts_iter.next().unwrap();
+    let buyer_record_pda = accounts_iter.next().unwrap();
+    let buyer_token_account = accounts_iter.next().unwrap();
+    let token_mint = accounts_iter.next().unwrap();
+    let token_deposit_ata = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+    let proceeds_vault_pda = accounts_iter.next().unwrap();
+    let token_program = accounts_iter.next().unwrap();
+    let payer_refund_account = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+    if !buyer.is_signer(){
+        msg!("Buyer must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_address_is_any(token_program, &[pinocchio_token::id(), spl_token_2022::id().to_bytes()])?;
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.mint_address != *token_mint.key(){
+        msg!("Unexpected token mint address");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if !flash_sale_settings.soft_cap_failed(now) {
+        msg!("Refunds are only available when the soft cap was missed");
+        return Err(FlashSaleError::RefundUnavailable.into());
+    }
+
+    let buyer_record = load_buyer_record(buyer.key(), buyer_record_pda, flash_sale_pda)?;
+
+    let seed = flash_sale_settings.seed();
+    let id_seed = flash_sale_settings.id_seed();
+    let expected_deposit_account = find_program_address(
+        &[b"deposit", &seed, token_mint.key(), &flash_sale_settings.owner_address, &id_seed],
+        &crate::id(),
+    );
+    check_token_account_owner(token_deposit_ata, &expected_deposit_account.0)?;
+
+    let returned_amount = flash_sale_settings.returned_tokens(&buyer_record);
+    let return_fee = get_transfer_fee(token_mint, returned_amount)?.unwrap_or(0);
+    flash_sale_settings.refund_purchase(&buyer_record, return_fee);
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    let payment_accounts = PaymentTokenAccounts::from_iter(&flash_sale_settings, accounts_iter)?;
+    let hook_accounts = accounts_iter.as_slice();
+
+    if returned_amount > 0 {
+        transfer_tokens(
+            token_program,
+            buyer_token_account,
+            token_mint,
+            token_deposit_ata,
+            buyer,
+            returned_amount,
+            get_mint_decimals(token_mint)?,
+            hook_accounts,
+            &[],
+        )?;
+    }
+
+    // The payment goes back to whoever funded the purchases, which is not the
+    // buyer when someone else bought for it.
+    match payment_accounts {
+        Some(_) => check_token_account_owner(payer_refund_account, &buyer_record.payer_address)?,
+        None => check_address(payer_refund_account, buyer_record.payer_address)?,
+    }
+    let refund_amount = buyer_record.paid + buyer_record.protocol_fees + buyer_record.referral_fees;
+    pay_out(
+        flash_sale_pda,
+        proceeds_vault_pda,
+        payer_refund_account,
+        payment_accounts.as_ref(),
+        refund_amount,
+    )?;
+    emit_withdrawal(
+        flash_sale_pda.key(),
+        WithdrawalKind::Refund,
+        payer_refund_account.key(),
+        refund_amount,
+        now,
+    )?;
+
+    deinit_account_if_exists(buyer_record_pda, buyer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buyer_record::tests::{record, record_data};
+    use crate::buyer_record::BuyerRecord;
+    use crate::init_flash_sale::tests::{sale, sale_data};
+    use crate::utils::tests::{mint_data, program_address, test_accounts, token_account_data, TestAccount};
+    use crate::utils::transfer_fee_for;
+    use pinocchio::pubkey::Pubkey;
+
+    const SALE: Pubkey = [5; 32];
+    const BUYER: Pubkey = [7; 32];
+    const PAYER: Pubkey = [9; 32];
+
+    // A SOL sale that ended at 2_000 with 2_500 of its 5_000 soft cap raised,
+    // all of it by PAYER buying 250 tokens for BUYER.
+    fn failed_sale() -> FlashSale {
+        FlashSale { soft_cap: 5_000, total_proceeds: 2_500, ..sale("Ticket") }
+    }
+
+    fn buyer_record(purchased: u64, received: u64, paid: u64) -> BuyerRecord {
+        BuyerRecord { purchased, received, paid, ..record(SALE, BUYER) }
+    }
+
+    fn gifted() -> BuyerRecord {
+        BuyerRecord { purchased: 250, received: 250, paid: 2_500, payer_address: PAYER, ..record(SALE, BUYER) }
+    }
+
+    fn refund_accounts(sale: &FlashSale, record: &BuyerRecord) -> Vec<TestAccount> {
+        let token_program = pinocchio_token::id();
+        let mint = sale.mint_address;
+        let deposit = program_address(&[b"deposit", &sale.seed(), &mint, &sale.owner_address, &sale.id_seed()]);
+        let mut accounts = vec![
+            TestAccount::wallet(BUYER).signer(),
+            TestAccount::new(program_address(&[b"buyer", &SALE, &BUYER]), crate::id(), record_data(record)),
+            TestAccount::new([4; 32], token_program, token_account_data(&mint, &BUYER, 250)),
+            TestAccount::new(mint, token_program, mint_data(None, 0)),
+            TestAccount::new([8; 32], token_program, token_account_data(&mint, &deposit, 750)),
+            TestAccount::new(SALE, crate::id(), sale_data(sale)),
+            TestAccount::new(program_address(&[b"proceeds", &SALE]), crate::id(), Vec::new()),
+            TestAccount::new(token_program, [0; 32], Vec::new()),
+            TestAccount::wallet(record.payer_address),
+        ];
+        accounts.extend((0..3).map(|_| TestAccount::wallet(crate::id())));
+        accounts
+    }
+
+    // Walks a purchase and its refund through a mint that withholds 1% of
+    // every transfer, tracking the deposit balance like the token program.
+    #[test]
+    fn refund_keeps_the_supply_in_line_with_a_fee_bearing_deposit() {
+        let mint = mint_data(Some(100), u64::MAX);
+        let fee = |amount| transfer_fee_for(&mint, 0, amount).unwrap().unwrap();
+
+        let deposited = 10_000;
+        let mut sale = FlashSale {
+            total_supply: deposited - fee(deposited),
+            total_sold: 0,
+            soft_cap: 1_000_000,
+            ..sale("Ticket")
+        };
+        let mut deposit_balance = deposited - fee(deposited);
+
+        let amount = 1_000;
+        deposit_balance -= amount;
+        sale.total_sold += amount;
+        sale.total_proceeds += 500;
+        let record = buyer_record(amount, amount - fee(amount), 500);
+        assert_eq!(sale.total_supply - sale.total_sold, deposit_balance);
+
+        let returned = sale.returned_tokens(&record);
+        assert_eq!(returned, 990);
+        deposit_balance += returned - fee(returned);
+        sale.refund_purchase(&record, fee(returned));
+
+        assert_eq!(sale.total_sold, 0);
+        assert_eq!(sale.total_proceeds, 0);
+        assert_eq!(sale.total_supply - sale.total_sold, deposit_balance);
+    }
+
+    #[test]
+    fn vested_refunds_return_nothing_and_keep_the_supply() {
+        let mut sale = FlashSale {
+            vesting_duration: 100,
+            total_sold: 1_000,
+            total_proceeds: 500,
+            ..sale("Ticket")
+        };
+        let record = buyer_record(1_000, 0, 500);
+
+        assert_eq!(sale.returned_tokens(&record), 0);
+        sale.refund_purchase(&record, 0);
+        assert_eq!(sale.total_sold, 0);
+        assert_eq!(sale.total_supply, 1_000);
+    }
+
+    #[test]
+    fn refunds_return_the_protocol_fees_held_for_soft_cap_sales() {
+        let mut sale = FlashSale {
+            soft_cap: 10_000,
+            total_sold: 1_000,
+            total_proceeds: 500,
+            held_protocol_fees: 15,
+            ..sale("Ticket")
+        };
+        assert!(sale.holds_fees());
+        let record = BuyerRecord {
+            protocol_fees: 5,
+            ..buyer_record(1_000, 1_000, 500)
+        };
+
+        sale.refund_purchase(&record, 0);
+        assert_eq!(sale.total_proceeds, 0);
+        assert_eq!(sale.held_protocol_fees, 10);
+    }
+
+    #[test]
+    fn refunds_go_to_the_wallet_that_paid() {
+        let accounts = test_accounts(&refund_accounts(&failed_sale(), &gifted()));
+        let accounts = &accounts.infos;
+        refund_at(accounts, 2_500).unwrap();
+
+        assert_eq!(accounts[8].lamports(), 1_000_002_500);
+        assert_eq!(accounts[6].lamports(), 999_997_500);
+        let refunded = FlashSale::try_from(accounts[5].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!((refunded.total_sold, refunded.total_proceeds), (0, 0));
+        // The record is closed, its rent going back to the buyer.
+        assert_eq!(accounts[1].lamports(), 0);
+        assert_eq!(accounts[0].lamports(), 2_000_000_000);
+    }
+
+    #[test]
+    fn refunds_cannot_be_redirected_from_the_payer() {
+        let mut accounts = refund_accounts(&failed_sale(), &gifted());
+        accounts[8] = TestAccount::wallet(BUYER);
+        assert_eq!(refund_at(&test_accounts(&accounts).infos, 2_500).err(), Some(ProgramError::IllegalOwner));
+
+        accounts.truncate(8);
+        assert_eq!(
+            refund_at(&test_accounts(&accounts).infos, 2_500).err(),
+            Some(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    #[test]
+    fn refunds_check_the_sale_and_its_accounts() {
+        let run = |accounts: &[TestAccount], now| refund_at(&test_accounts(accounts).infos, now).err();
+        let sale = failed_sale();
+        let record = gifted();
+
+        let mut unsigned = refund_accounts(&sale, &record);
+        unsigned[0] = TestAccount::wallet(BUYER);
+        assert_eq!(run(&unsigned, 2_500), Some(ProgramError::MissingRequiredSignature));
+
+        // Before the end, or once the soft cap is reached, there is no refund.
+        let accounts = refund_accounts(&sale, &record);
+        assert_eq!(run(&accounts, 1_999), Some(FlashSaleError::RefundUnavailable.into()));
+        let funded = FlashSale { total_proceeds: 5_000, ..sale.clone() };
+        assert_eq!(run(&refund_accounts(&funded, &record), 2_500), Some(FlashSaleError::RefundUnavailable.into()));
+        let uncapped = FlashSale { soft_cap: 0, ..sale.clone() };
+        assert_eq!(run(&refund_accounts(&uncapped, &record), 2_500), Some(FlashSaleError::RefundUnavailable.into()));
+
+        let mut other_mint = refund_accounts(&sale, &record);
+        other_mint[3].key = [6; 32];
+        assert_eq!(run(&other_mint, 2_500), Some(ProgramError::InvalidArgument));
+
+        let mut other_record = refund_accounts(&sale, &record);
+        other_record[1].key = program_address(&[b"buyer", &SALE, &[8; 32]]);
+        assert_eq!(run(&other_record, 2_500), Some(ProgramError::InvalidSeeds));
+
+        let mut other_deposit = refund_accounts(&sale, &record);
+        other_deposit[4].data = token_account_data(&sale.mint_address, &[8; 32], 750);
+        assert_eq!(run(&other_deposit, 2_500), Some(ProgramError::IllegalOwner));
+
+        let mut forged_sale = refund_accounts(&sale, &record);
+        forged_sale[5].owner = [8; 32];
+        assert_eq!(run(&forged_sale, 2_500), Some(ProgramError::IllegalOwner));
+
+        let mut other_vault = refund_accounts(&sale, &record);
+        other_vault[6].key = program_address(&[b"proceeds", &[8; 32]]);
+        assert_eq!(run(&other_vault, 2_500), Some(ProgramError::InvalidSeeds));
+    }
+}
@@ -0,0 +1,291 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::instruction::Signer;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::sysvars::rent::Rent;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, seeds};
+use shank::ShankAccount;
+use solana_program::pubkey::Pubkey as SPK;
+
+use crate::utils::{check_owner, find_program_address};
+
+// The global registry lists every sale; it is keyed by the zero address in
+// place of an owner.
+pub const GLOBAL_REGISTRY_OWNER: Pubkey = [0; 32];
+pub const REGISTRY_PAGE_CAPACITY: usize = 32;
+// Registry index of a sale that was never registered.
+pub const NOT_REGISTERED: u64 = u64::MAX;
+
+#[derive(Clone, ShankAccount)]
+pub struct Registry {
+    pub owner_address: Pubkey,
+    pub total: u64,
+    pub active: u64,
+}
+
+pub const REGISTRY_ACCOUNT_SIZE: usize = 32 + 8 + 8;
+
+impl TryFrom<&[u8]> for Registry {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < REGISTRY_ACCOUNT_SIZE {
+            return Err("Registry too small".to_string());
+        }
+        let owner_address: Pubkey = data[0..32].try_into().unwrap();
+        let total = u64::from_le_bytes(data[32..40].try_into().unwrap());
+        let active = u64::from_le_bytes(data[40..48].try_into().unwrap());
+
+        Ok(Registry {
+            owner_address,
+            total,
+            active,
+        })
+    }
+}
+
+impl Registry {
+    pub fn write_to_slice(&self, buf: &mut [u8]) -> Result<(), ProgramError> {
+        buf[0..32].copy_from_slice(&self.owner_address);
+        buf[32..40].copy_from_slice(&self.total.to_le_bytes());
+        buf[40..48].copy_from_slice(&self.active.to_le_bytes());
+
+        Ok(())
+    }
+
+    pub fn page_count(&self) -> u32 {
+        self.total.div_ceil(REGISTRY_PAGE_CAPACITY as u64) as u32
+    }
+}
+
+// Sales in registration order. Entries of closed sales are zeroed in place so
+// the position of every other sale never changes.
+#[derive(Clone, ShankAccount)]
+pub struct RegistryPage {
+    pub owner_address: Pubkey,
+    pub page: u32,
+    pub sales: [Pubkey; 32],
+}
+
+const REGISTRY_PAGE_HEADER_SIZE: usize = 32 + 4;
+pub const REGISTRY_PAGE_ACCOUNT_SIZE: usize = REGISTRY_PAGE_HEADER_SIZE + REGISTRY_PAGE_CAPACITY * 32;
+
+impl TryFrom<&[u8]> for RegistryPage {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < REGISTRY_PAGE_ACCOUNT_SIZE {
+            return Err("Registry page too small".to_string());
+        }
+        let owner_address: Pubkey = data[0..32].try_into().unwrap();
+        let page = u32::from_le_bytes(data[32..36].try_into().unwrap());
+        let mut sales = [[0; 32]; REGISTRY_PAGE_CAPACITY];
+        for (slot, sale) in sales.iter_mut().enumerate() {
+            *sale = data[entry_offset(slot)..entry_offset(slot) + 32].try_into().unwrap();
+        }
+
+        Ok(RegistryPage {
+            owner_address,
+            page,
+            sales,
+        })
+    }
+}
+
+impl RegistryPage {
+    // Registered sales on this page, skipping closed ones.
+    pub fn active_sales(&self) -> impl Iterator<Item = &Pubkey> {
+        self.sales.iter().filter(|sale| **sale != [0; 32])
+    }
+}
+
+fn entry_offset(slot: usize) -> usize {
+    REGISTRY_PAGE_HEADER_SIZE + slot * 32
+}
+
+fn find_registry(owner: &Pubkey, registry_pda: &AccountInfo) -> Result<u8, ProgramError> {
+    let expected_registry_account = find_program_address(&[b"registry", owner], &crate::id());
+    if expected_registry_account.0 != *registry_pda.key() {
+        msg!("Unexpected registry address");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(expected_registry_account.1)
+}
+
+fn find_registry_page(owner: &Pubkey, page: u32, registry_page_pda: &AccountInfo) -> Result<u8, ProgramError> {
+    let expected_page_account = find_program_address(
+        &[b"registry", owner, &page.to_le_bytes()],
+        &crate::id(),
+    );
+    if expected_page_account.0 != *registry_page_pda.key() {
+        msg!("Unexpected registry page address");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(expected_page_account.1)
+}
+
+fn load_or_create_registry(payer: &AccountInfo, owner: &Pubkey, registry_pda: &AccountInfo) -> Result<Registry, ProgramError> {
+    let registry_bump = find_registry(owner, registry_pda)?;
+    if registry_pda.owner() == &crate::id() {
+        let data = registry_pda.try_borrow_data()?;
+        return Registry::try_from(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData);
+    }
+
+    let registry_binding = [registry_bump];
+    let registry_seeds = seeds!(b"registry", owner, &registry_binding);
+
+    let rent = Rent::get()?;
+    pinocchio_system::instructions::CreateAccount {
+        from: payer,
+        to: registry_pda,
+        space: REGISTRY_ACCOUNT_SIZE as u64,
+        lamports: rent.minimum_balance(REGISTRY_ACCOUNT_SIZE),
+        owner: &crate::id(),
+    }
+    .invoke_signed(&[Signer::from(&registry_seeds)])?;
+
+    Ok(Registry {
+        owner_address: *owner,
+        total: 0,
+        active: 0,
+    })
+}
+
+fn create_registry_page(payer: &AccountInfo, owner: &Pubkey, page: u32, registry_page_pda: &AccountInfo) -> Result<(), ProgramError> {
+    let page_bump = find_registry_page(owner, page, registry_page_pda)?;
+    let page_index = page.to_le_bytes();
+    let page_binding = [page_bump];
+    let page_seeds = seeds!(b"registry", owner, &page_index, &page_binding);
+
+    let rent = Rent::get()?;
+    pinocchio_system::instructions::CreateAccount {
+        from: payer,
+        to: registry_page_pda,
+        space: REGISTRY_PAGE_ACCOUNT_SIZE as u64,
+        lamports: rent.minimum_balance(REGISTRY_PAGE_ACCOUNT_SIZE),
+        owner: &crate::id(),
+    }
+    .invoke_signed(&[Signer::from(&page_seeds)])?;
+
+    let mut data = registry_page_pda.try_borrow_mut_data()?;
+    data[0..32].copy_from_slice(owner);
+    data[32..36].copy_from_slice(&page_index);
+    Ok(())
+}
+
+// Appends `sale` to the owner's registry and returns its index. The page for
+// the index is created by the payer when the previous one is full.
+pub fn register_sale(
+    payer: &AccountInfo,
+    owner: &Pubkey,
+    registry_pda: &AccountInfo,
+    registry_page_pda: &AccountInfo,
+    sale: &Pubkey,
+) -> Result<u64, ProgramError> {
+    let mut registry = load_or_create_registry(payer, owner, registry_pda)?;
+
+    let index = registry.total;
+    let page = registry_page_for(index);
+    let slot = (index % REGISTRY_PAGE_CAPACITY as u64) as usize;
+    if slot == 0 {
+        create_registry_page(payer, owner, page, registry_page_pda)?;
+    } else {
+        find_registry_page(owner, page, registry_page_pda)?;
+        check_owner(registry_page_pda, crate::id())?;
+    }
+    registry_page_pda.try_borrow_mut_data()?[entry_offset(slot)..entry_offset(slot) + 32].copy_from_slice(sale);
+
+    registry.total += 1;
+    registry.active += 1;
+    registry.write_to_slice(&mut registry_pda.try_borrow_mut_data()?)?;
+    Ok(index)
+}
+
+pub fn unregister_sale(
+    owner: &Pubkey,
+    registry_pda: &AccountInfo,
+    registry_page_pda: &AccountInfo,
+    sale: &Pubkey,
+    index: u64,
+) -> Result<(), ProgramError> {
+    let page = registry_page_for(index);
+    let slot = (index % REGISTRY_PAGE_CAPACITY as u64) as usize;
+    find_registry(owner, registry_pda)?;
+    find_registry_page(owner, page, registry_page_pda)?;
+    check_owner(registry_pda, crate::id())?;
+    check_owner(registry_page_pda, crate::id())?;
+
+    let mut page_data = registry_page_pda.try_borrow_mut_data()?;
+    let entry = &mut page_data[entry_offset(slot)..entry_offset(slot) + 32];
+    if entry != sale.as_slice() {
+        msg!("Sale is not registered at its registry index");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    entry.fill(0);
+
+    let mut registry_data = registry_pda.try_borrow_mut_data()?;
+    let mut registry = Registry::try_from(registry_data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)?;
+    registry.active -= 1;
+    registry.write_to_slice(&mut registry_data)
+}
+
+// Client-side helpers. Fetch the registry, then pages 0..page_count() and
+// read their active_sales() in order.
+pub fn find_registry_address(owner: &SPK) -> (SPK, u8) {
+    SPK::find_program_address(&[b"registry", owner.as_ref()], &SPK::new_from_array(crate::id()))
+}
+
+pub fn find_global_registry_address() -> (SPK, u8) {
+    find_registry_address(&SPK::new_from_array(GLOBAL_REGISTRY_OWNER))
+}
+
+pub fn find_registry_page_address(owner: &SPK, page: u32) -> (SPK, u8) {
+    SPK::find_program_address(
+        &[b"registry", owner.as_ref(), &page.to_le_bytes()],
+        &SPK::new_from_array(crate::id()),
+    )
+}
+
+// The page holding registry index `index`; a new sale goes to the page for the
+// registry's current `total`.
+pub fn registry_page_for(index: u64) -> u32 {
+    (index / REGISTRY_PAGE_CAPACITY as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexes_fill_one_page_at_a_time() {
+        let capacity = REGISTRY_PAGE_CAPACITY as u64;
+        assert_eq!(registry_page_for(0), 0);
+        assert_eq!(registry_page_for(capacity - 1), 0);
+        assert_eq!(registry_page_for(capacity), 1);
+
+        let registry = |total| Registry {
+            owner_address: [1; 32],
+            total,
+            active: total,
+        };
+        assert_eq!(registry(0).page_count(), 0);
+        assert_eq!(registry(1).page_count(), 1);
+        assert_eq!(registry(capacity).page_count(), 1);
+        assert_eq!(registry(capacity + 1).page_count(), 2);
+    }
+
+    #[test]
+    fn pages_skip_closed_sales() {
+        let mut data = vec![0; REGISTRY_PAGE_ACCOUNT_SIZE];
+        data[32..36].copy_from_slice(&2u32.to_le_bytes());
+        for (slot, sale) in [[5; 32], [0; 32], [7; 32]].iter().enumerate() {
+            data[entry_offset(slot)..entry_offset(slot) + 32].copy_from_slice(sale);
+        }
+
+        let page = RegistryPage::try_from(data.as_slice()).unwrap();
+        assert_eq!(page.page, 2);
+        assert_eq!(page.active_sales().collect::<Vec<_>>(), [&[5; 32], &[7; 32]]);
+        assert!(RegistryPage::try_from(&data[..REGISTRY_PAGE_ACCOUNT_SIZE - 1]).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::{msg, ProgramResult};
+use shank::ShankType;
+
+use crate::error::FlashSaleError;
+use crate::events::{emit_withdrawal, WithdrawalKind};
+use crate::init_flash_sale::FlashSale;
+use crate::proceeds_vault::{pay_out, PaymentTokenAccounts};
+use crate::utils::{check_token_account_owner, BPS_DENOMINATOR};
+
+pub const MAX_PAYEES: usize = 5;
+pub const PAYEE_SIZE: usize = 32 + 2;
+
+pub type Payees = [Payee; MAX_PAYEES];
+
+#[derive(Debug, Clone, Copy, Default, ShankType)]
+pub struct Payee {
+    pub address: Pubkey,
+    pub share_bps: u16,
+}
+
+impl Payee {
+    pub fn read(data: &[u8]) -> Payee {
+        Payee {
+            address: data[0..32].try_into().unwrap(),
+            share_bps: u16::from_le_bytes(data[32..34].try_into().unwrap()),
+        }
+    }
+
+    pub fn write(&self, buf: &mut [u8]) {
+        buf[0..32].copy_from_slice(&self.address);
+        buf[32..34].copy_from_slice(&self.share_bps.to_le_bytes());
+    }
+}
+
+pub fn validate_payees(payees: &[Payee]) -> ProgramResult {
+    if payees.is_empty() {
+        return Ok(());
+    }
+    if payees.len() > MAX_PAYEES {
+        msg!("Too many payees");
+        return Err(FlashSaleError::InvalidPayees.into());
+    }
+    let total_bps: u32 = payees.iter().map(|payee| payee.share_bps as u32).sum();
+    if total_bps != BPS_DENOMINATOR as u32 {
+        msg!("Payee shares must add up to 10000 bps");
+        return Err(FlashSaleError::InvalidPayees.into());
+    }
+    Ok(())
+}
+
+// Every payee gets the floor of its share; the rounding dust goes to the
+// first payee so the split always adds up to `amount`.
+pub fn split_amount(amount: u64, payees: &[Payee]) -> Vec<u64> {
+    let mut shares: Vec<u64> = payees
+        .iter()
+        .map(|payee| (amount as u128 * payee.share_bps as u128 / BPS_DENOMINATOR as u128) as u64)
+        .collect();
+    let distributed: u64 = shares.iter().sum();
+    if let Some(first) = shares.first_mut() {
+        *first += amount - distributed;
+    }
+    shares
+}
+
+pub fn distribute_proceeds<'a, I: Iterator<Item = &'a AccountInfo>>(
+    flash_sale_settings: &FlashSale,
+    flash_sale_pda: &AccountInfo,
+    proceeds_vault_pda: &AccountInfo,
+    payment_accounts: Option<&PaymentTokenAccounts>,
+    payee_accounts: &mut I,
+    amount: u64,
+    now: i64,
+) -> ProgramResult {
+    let payees = flash_sale_settings.payees();
+    for (payee, share) in payees.iter().zip(split_amount(amount, payees)) {
+        let payee_account = payee_accounts.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        match payment_accounts {
+            None => {
+                if *payee_account.key() != payee.address {
+                    msg!("Unexpected payee account");
+                    return Err(FlashSaleError::InvalidPayees.into());
+                }
+            }
+            Some(_) => check_token_account_owner(payee_account, &payee.address)?,
+        }
+
+        if share > 0 {
+            pay_out(flash_sale_pda, proceeds_vault_pda, payee_account, payment_accounts, share)?;
+            emit_withdrawal(flash_sale_pda.key(), WithdrawalKind::Proceeds, payee_account.key(), share, now)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payees(shares: &[u16]) -> Vec<Payee> {
+        shares
+            .iter()
+            .enumerate()
+            .map(|(index, share_bps)| Payee {
+                address: [index as u8 + 1; 32],
+                share_bps: *share_bps,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_gives_the_rounding_dust_to_the_first_payee() {
+        let payees = payees(&[3_333, 3_333, 3_334]);
+        assert_eq!(split_amount(100, &payees), [34, 33, 33]);
+        assert_eq!(split_amount(u64::MAX, &payees).iter().map(|share| *share as u128).sum::<u128>(), u64::MAX as u128);
+        assert!(split_amount(100, &[]).is_empty());
+    }
+
+    #[test]
+    fn payee_shares_must_add_up_to_the_whole() {
+        assert!(validate_payees(&[]).is_ok());
+        assert!(validate_payees(&payees(&[5_000, 5_000])).is_ok());
+        assert!(validate_payees(&payees(&[5_000, 4_999])).is_err());
+        assert!(validate_payees(&payees(&[2_000; 6])).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::instruction::Signer;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::sysvars::rent::Rent;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, seeds};
+use shank::ShankAccount;
+use solana_program::pubkey::Pubkey as SPK;
+
+use crate::utils::find_program_address;
+
+// Hands out the owner's sale ids. Every sale address is seeded with its id, so
+// an address can only ever be initialized once, whatever the seed scheme.
+#[derive(Clone, ShankAccount)]
+pub struct SaleCounter {
+    pub owner_address: Pubkey,
+    pub next_sale_id: u64,
+}
+
+pub const SALE_COUNTER_ACCOUNT_SIZE: usize = 32 + 8;
+
+impl TryFrom<&[u8]> for SaleCounter {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < SALE_COUNTER_ACCOUNT_SIZE {
+            return Err("Sale counter too small".to_string());
+        }
+        let owner_address: Pubkey = data[0..32].try_into().unwrap();
+        let next_sale_id = u64::from_le_bytes(data[32..40].try_into().unwrap());
+
+        Ok(SaleCounter {
+            owner_address,
+            next_sale_id,
+        })
+    }
+}
+
+impl SaleCounter {
+    pub fn write_to_slice(&self, buf: &mut [u8]) -> Result<(), ProgramError> {
+        buf[0..32].copy_from_slice(&self.owner_address);
+        buf[32..40].copy_from_slice(&self.next_sale_id.to_le_bytes());
+
+        Ok(())
+    }
+}
+
+fn find_sale_counter(owner: &Pubkey, sale_counter_pda: &AccountInfo) -> Result<u8, ProgramError> {
+    let expected_counter_account = find_program_address(&[b"counter", owner], &crate::id());
+    if expected_counter_account.0 != *sale_counter_pda.key() {
+        msg!("Unexpected sale counter address");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(expected_counter_account.1)
+}
+
+fn load_or_create_sale_counter(
+    payer: &AccountInfo,
+    owner: &Pubkey,
+    sale_counter_pda: &AccountInfo,
+) -> Result<SaleCounter, ProgramError> {
+    let counter_bump = find_sale_counter(owner, sale_counter_pda)?;
+    if sale_counter_pda.owner() == &crate::id() {
+        let data = sale_counter_pda.try_borrow_data()?;
+        return SaleCounter::try_from(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData);
+    }
+
+    let counter_binding = [counter_bump];
+    let counter_seeds = seeds!(b"counter", owner, &counter_binding);
+
+    let rent = Rent::get()?;
+    pinocchio_system::instructions::CreateAccount {
+        from: payer,
+        to: sale_counter_pda,
+        space: SALE_COUNTER_ACCOUNT_SIZE as u64,
+        lamports: rent.minimum_balance(SALE_COUNTER_ACCOUNT_SIZE),
+        owner: &crate::id(),
+    }
+    .invoke_signed(&[Signer::from(&counter_seeds)])?;
+
+    Ok(SaleCounter {
+        owner_address: *owner,
+        next_sale_id: 0,
+    })
+}
+
+// Claims `sale_id` for a new sale of `owner`. It must be the counter's next
+// id, which then moves on; ids are never reused, even after a sale closes.
+pub fn take_sale_id(
+    payer: &AccountInfo,
+    owner: &Pubkey,
+    sale_counter_pda: &AccountInfo,
+    sale_id: u64,
+) -> Result<(), ProgramError> {
+    let mut counter = load_or_create_sale_counter(payer, owner, sale_counter_pda)?;
+    if sale_id != counter.next_sale_id {
+        msg!("Sale id must be the owner's next sale id");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    counter.next_sale_id += 1;
+    counter.write_to_slice(&mut sale_counter_pda.try_borrow_mut_data()?)
+}
+
+// Client-side helper. The next sale id is the counter's `next_sale_id`, or 0
+// when the account does not exist yet.
+pub fn find_sale_counter_address(owner: &SPK) -> (SPK, u8) {
+    SPK::find_program_address(&[b"counter", owner.as_ref()], &SPK::new_from_array(crate::id()))
+}
@@ -0,0 +1,88 @@
+use solana_program::hash::hash;
+use solana_program::pubkey::Pubkey as SPK;
+use shank::ShankType;
+
+// Seeds are limited to 32 bytes, so raw names cap the display name as well.
+pub const MAX_NAME_SEED_LENGTH: usize = 32;
+
+// How the name seed of the sale and deposit PDAs is built. The scheme is
+// stored in the sale account so every instruction re-derives the same
+// addresses. Every scheme is followed by the sale id from the owner's
+// `SaleCounter`: ["sale", name_seed, mint, owner, sale_id as u64 LE].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ShankType)]
+pub enum SeedScheme {
+    // name_seed = item_name
+    Name,
+    // name_seed = sha256(item_name)
+    NameHash,
+    // name_seed is empty; the sale id alone tells the owner's sales apart.
+    SaleId,
+}
+
+impl TryFrom<u8> for SeedScheme {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SeedScheme::Name),
+            1 => Ok(SeedScheme::NameHash),
+            2 => Ok(SeedScheme::SaleId),
+            _ => Err("Invalid seed scheme".to_string()),
+        }
+    }
+}
+
+// The name part of the seeds of a sale.
+pub fn sale_seed(scheme: SeedScheme, item_name: &str) -> Vec<u8> {
+    match scheme {
+        SeedScheme::Name => item_name.as_bytes().to_vec(),
+        SeedScheme::NameHash => hash(item_name.as_bytes()).to_bytes().to_vec(),
+        SeedScheme::SaleId => Vec::new(),
+    }
+}
+
+// Client-side helpers; on-chain code derives the same addresses from
+// `sale_seed` with pinocchio. Sales created before the layout was versioned
+// have no sale id seed.
+pub fn find_sale_address(sale_seed: &[u8], sale_id: u64, mint: &SPK, owner: &SPK) -> (SPK, u8) {
+    SPK::find_program_address(
+        &[b"sale", sale_seed, mint.as_ref(), owner.as_ref(), &sale_id.to_le_bytes()],
+        &SPK::new_from_array(crate::id()),
+    )
+}
+
+pub fn find_deposit_address(sale_seed: &[u8], sale_id: u64, mint: &SPK, owner: &SPK) -> (SPK, u8) {
+    SPK::find_program_address(
+        &[b"deposit", sale_seed, mint.as_ref(), owner.as_ref(), &sale_id.to_le_bytes()],
+        &SPK::new_from_array(crate::id()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_seed_follows_the_scheme() {
+        assert_eq!(sale_seed(SeedScheme::Name, "Ticket"), b"Ticket");
+        assert_eq!(sale_seed(SeedScheme::NameHash, "Ticket"), hash(b"Ticket").to_bytes());
+        assert!(sale_seed(SeedScheme::SaleId, "Ticket").is_empty());
+    }
+
+    #[test]
+    fn sale_id_tells_sales_with_the_same_name_apart() {
+        let mint = SPK::new_from_array([1; 32]);
+        let owner = SPK::new_from_array([2; 32]);
+        for scheme in [SeedScheme::Name, SeedScheme::NameHash, SeedScheme::SaleId] {
+            let seed = sale_seed(scheme, "Ticket");
+            assert_ne!(
+                find_sale_address(&seed, 0, &mint, &owner).0,
+                find_sale_address(&seed, 1, &mint, &owner).0
+            );
+            assert_ne!(
+                find_sale_address(&seed, 0, &mint, &owner).0,
+                find_deposit_address(&seed, 0, &mint, &owner).0
+            );
+        }
+    }
+}
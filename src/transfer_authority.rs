@@ -0,0 +1,88 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::{msg, ProgramResult};
+use shank::ShankType;
+
+use crate::error::FlashSaleError;
+use crate::events::emit_sale_updated;
+use crate::init_flash_sale::FlashSale;
+use crate::utils::check_owner;
+
+#[derive(Debug, Clone, ShankType)]
+pub struct ProposeAuthorityArgs {
+    pub new_authority: Pubkey,
+}
+
+impl TryFrom<&[u8]> for ProposeAuthorityArgs {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let new_authority: Pubkey = data
+            .get(0..32)
+            .ok_or("Unexpected end of data")?
+            .try_into()
+            .unwrap();
+
+        Ok(ProposeAuthorityArgs { new_authority })
+    }
+}
+
+pub fn propose_authority(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let authority = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+
+    let args = ProposeAuthorityArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    if !authority.is_signer(){
+        msg!("Authority must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.authority_address != *authority.key(){
+        msg!("Unexpected flash sale authority");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+
+    flash_sale_settings.pending_authority = args.new_authority;
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    Ok(())
+}
+
+pub fn accept_authority(accounts: &[AccountInfo], _instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let new_authority = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+
+    if !new_authority.is_signer(){
+        msg!("New authority must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.pending_authority == [0; 32]
+        || flash_sale_settings.pending_authority != *new_authority.key()
+    {
+        msg!("Signer is not the pending authority");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+
+    flash_sale_settings.authority_address = flash_sale_settings.pending_authority;
+    flash_sale_settings.pending_authority = [0; 32];
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    emit_sale_updated(flash_sale_pda.key(), &flash_sale_settings)
+}
@@ -0,0 +1,80 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::{msg, ProgramResult};
+use shank::ShankType;
+
+use crate::error::FlashSaleError;
+use crate::init_config::{load_config, MAX_FEE_BPS};
+use crate::utils::read_option;
+
+#[derive(Debug, Clone, ShankType)]
+pub struct UpdateConfigArgs {
+    pub admin: Option<Pubkey>,
+    pub protocol_fee_bps: Option<u16>,
+    pub fee_recipient: Option<Pubkey>,
+    pub paused: Option<bool>,
+}
+
+impl TryFrom<&[u8]> for UpdateConfigArgs {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let mut offset = 0;
+        let admin = read_option(data, &mut offset, 32)?
+            .map(|v| -> Pubkey { v.try_into().unwrap() });
+        let protocol_fee_bps = read_option(data, &mut offset, 2)?
+            .map(|v| u16::from_le_bytes(v.try_into().unwrap()));
+        let fee_recipient = read_option(data, &mut offset, 32)?
+            .map(|v| -> Pubkey { v.try_into().unwrap() });
+        let paused = read_option(data, &mut offset, 1)?.map(|v| v[0] != 0);
+
+        Ok(UpdateConfigArgs {
+            admin,
+            protocol_fee_bps,
+            fee_recipient,
+            paused,
+        })
+    }
+}
+
+pub fn update_config(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let admin = accounts_iter.next().unwrap();
+    let config_pda = accounts_iter.next().unwrap();
+
+    let args = UpdateConfigArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    if !admin.is_signer(){
+        msg!("Admin must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut config = load_config(config_pda)?.ok_or(ProgramError::UninitializedAccount)?;
+    if config.admin != *admin.key(){
+        msg!("Unexpected config admin");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+
+    if let Some(new_admin) = args.admin {
+        config.admin = new_admin;
+    }
+    if let Some(protocol_fee_bps) = args.protocol_fee_bps {
+        if protocol_fee_bps > MAX_FEE_BPS {
+            msg!("Protocol fee exceeds 100%");
+            return Err(FlashSaleError::InvalidFee.into());
+        }
+        config.protocol_fee_bps = protocol_fee_bps;
+    }
+    if let Some(fee_recipient) = args.fee_recipient {
+        config.fee_recipient = fee_recipient;
+    }
+    if let Some(paused) = args.paused {
+        config.paused = paused;
+    }
+
+    config.write_to_slice(&mut config_pda.try_borrow_mut_data()?)?;
+
+    Ok(())
+}
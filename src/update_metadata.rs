@@ -0,0 +1,150 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::sysvars::rent::Rent;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, ProgramResult};
+use shank::ShankType;
+
+use crate::error::FlashSaleError;
+use crate::events::emit_sale_updated;
+use crate::init_flash_sale::FlashSale;
+use crate::utils::{check_address, check_owner, read_option};
+
+pub const MAX_DESCRIPTION_LENGTH: usize = 512;
+pub const MAX_URI_LENGTH: usize = 200;
+
+// Storefront metadata kept after the fixed part of the sale account.
+// `content_hash` is the sha256 of the JSON behind `external_url`, if any.
+#[derive(Debug, Clone, Default, PartialEq, Eq, ShankType)]
+pub struct SaleMetadata {
+    pub description: String,
+    pub image_uri: String,
+    pub external_url: String,
+    pub content_hash: Option<[u8; 32]>,
+}
+
+fn read_string(data: &[u8], offset: &mut usize, max_len: usize) -> Result<String, String> {
+    let len_bytes = data.get(*offset..*offset + 4).ok_or("Unexpected end of data")?;
+    let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+    *offset += 4;
+    if len > max_len {
+        return Err("Metadata field too long".to_string());
+    }
+    let value = data.get(*offset..*offset + len).ok_or("Unexpected end of data")?;
+    *offset += len;
+    String::from_utf8(value.to_vec()).map_err(|_| "Metadata is not valid UTF-8".to_string())
+}
+
+fn write_string(buf: &mut [u8], offset: &mut usize, value: &str) {
+    buf[*offset..*offset + 4].copy_from_slice(&(value.len() as u32).to_le_bytes());
+    *offset += 4;
+    buf[*offset..*offset + value.len()].copy_from_slice(value.as_bytes());
+    *offset += value.len();
+}
+
+impl SaleMetadata {
+    pub fn read(data: &[u8], offset: &mut usize) -> Result<Self, String> {
+        let description = read_string(data, offset, MAX_DESCRIPTION_LENGTH)?;
+        let image_uri = read_string(data, offset, MAX_URI_LENGTH)?;
+        let external_url = read_string(data, offset, MAX_URI_LENGTH)?;
+        let content_hash = read_option(data, offset, 32)?.map(|v| -> [u8; 32] { v.try_into().unwrap() });
+
+        Ok(SaleMetadata {
+            description,
+            image_uri,
+            external_url,
+            content_hash,
+        })
+    }
+
+    pub fn write(&self, buf: &mut [u8], offset: &mut usize) {
+        write_string(buf, offset, &self.description);
+        write_string(buf, offset, &self.image_uri);
+        write_string(buf, offset, &self.external_url);
+        match self.content_hash {
+            Some(hash) => {
+                buf[*offset] = 1;
+                buf[*offset + 1..*offset + 33].copy_from_slice(&hash);
+                *offset += 33;
+            }
+            None => {
+                buf[*offset] = 0;
+                *offset += 1;
+            }
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        4 + self.description.len() + 4 + self.image_uri.len() + 4 + self.external_url.len()
+            + 1 + self.content_hash.map_or(0, |_| 32)
+    }
+}
+
+impl TryFrom<&[u8]> for SaleMetadata {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        SaleMetadata::read(data, &mut 0)
+    }
+}
+
+// Grows or shrinks the sale account to fit its current contents. The payer
+// tops up rent when it grows and gets the excess back when it shrinks.
+pub fn resize_sale_account(
+    payer: &AccountInfo,
+    flash_sale_pda: &AccountInfo,
+    system_program: &AccountInfo,
+    new_size: usize,
+) -> ProgramResult {
+    let minimum_balance = Rent::get()?.minimum_balance(new_size);
+    let lamports = flash_sale_pda.lamports();
+
+    if minimum_balance > lamports {
+        check_address(system_program, pinocchio_system::id())?;
+        pinocchio_system::instructions::Transfer {
+            from: payer,
+            to: flash_sale_pda,
+            lamports: minimum_balance - lamports,
+        }
+        .invoke()?;
+    }
+
+    flash_sale_pda.resize(new_size)?;
+
+    if lamports > minimum_balance {
+        *flash_sale_pda.try_borrow_mut_lamports()? = minimum_balance;
+        *payer.try_borrow_mut_lamports()? += lamports - minimum_balance;
+    }
+
+    Ok(())
+}
+
+pub fn update_metadata(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let authority = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+    let system_program = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+    let metadata = SaleMetadata::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    if !authority.is_signer(){
+        msg!("Authority must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_pda.try_borrow_data()?.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.authority_address != *authority.key(){
+        msg!("Unexpected flash sale authority");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+
+    flash_sale_settings.metadata = metadata;
+    resize_sale_account(authority, flash_sale_pda, system_program, flash_sale_settings.size())?;
+    flash_sale_settings.write_to_slice(&mut flash_sale_pda.try_borrow_mut_data()?)?;
+
+    emit_sale_updated(flash_sale_pda.key(), &flash_sale_settings)
+}
@@ -0,0 +1,128 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::pubkey::Pubkey;
+use pinocchio::sysvars::clock::Clock;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, ProgramResult};
+use shank::ShankType;
+
+use crate::error::FlashSaleError;
+use crate::events::emit_sale_updated;
+use crate::init_flash_sale::FlashSale;
+use crate::utils::{check_owner, read_option};
+
+#[derive(Debug, Clone, ShankType)]
+pub struct UpdateSaleArgs {
+    pub price: Option<u64>,
+    pub end_timestamp: Option<i64>,
+    pub max_per_wallet: Option<u64>,
+    pub paused: Option<bool>,
+    pub operator: Option<Pubkey>,
+    pub price_denominator: Option<u64>,
+}
+
+impl TryFrom<&[u8]> for UpdateSaleArgs {
+    type Error = String;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let mut offset = 0;
+        let price = read_option(data, &mut offset, 8)?
+            .map(|v| u64::from_le_bytes(v.try_into().unwrap()));
+        let end_timestamp = read_option(data, &mut offset, 8)?
+            .map(|v| i64::from_le_bytes(v.try_into().unwrap()));
+        let max_per_wallet = read_option(data, &mut offset, 8)?
+            .map(|v| u64::from_le_bytes(v.try_into().unwrap()));
+        let paused = read_option(data, &mut offset, 1)?.map(|v| v[0] != 0);
+        let operator = read_option(data, &mut offset, 32)?
+            .map(|v| -> Pubkey { v.try_into().unwrap() });
+        let price_denominator = read_option(data, &mut offset, 8)?
+            .map(|v| u64::from_le_bytes(v.try_into().unwrap()));
+
+        Ok(UpdateSaleArgs {
+            price,
+            end_timestamp,
+            max_per_wallet,
+            paused,
+            operator,
+            price_denominator,
+        })
+    }
+}
+
+pub fn update_sale(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let authority = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+
+    let args = UpdateSaleArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    if !authority.is_signer(){
+        msg!("Authority must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.authority_address != *authority.key(){
+        msg!("Unexpected flash sale authority");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+
+    let clock = Clock::get()?;
+
+    // Allocations are priced when commitments settle, so a new price would
+    // change what existing commitments receive.
+    let changes_price = args.price.is_some() || args.price_denominator.is_some();
+    if changes_price && flash_sale_settings.commit_mode && flash_sale_settings.total_committed > 0 {
+        msg!("Cannot change the price of a commit sale with commitments");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if let Some(price) = args.price {
+        if !flash_sale_settings.is_active(clock.unix_timestamp) {
+            msg!("Cannot change the price of an ended sale");
+            return Err(ProgramError::InvalidArgument);
+        }
+        flash_sale_settings.price = price;
+    }
+    if let Some(price_denominator) = args.price_denominator {
+        if !flash_sale_settings.is_active(clock.unix_timestamp) {
+            msg!("Cannot change the price of an ended sale");
+            return Err(ProgramError::InvalidArgument);
+        }
+        flash_sale_settings.price_denominator = price_denominator;
+    }
+    if let Some(end_timestamp) = args.end_timestamp {
+        if !flash_sale_settings.is_active(clock.unix_timestamp) {
+            msg!("Cannot reopen an ended sale");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if end_timestamp < clock.unix_timestamp || end_timestamp < flash_sale_settings.init_timestamp {
+            msg!("End time must not be in the past");
+            return Err(ProgramError::InvalidArgument);
+        }
+        flash_sale_settings.end_timestamp = end_timestamp;
+    }
+    if let Some(max_per_wallet) = args.max_per_wallet {
+        if !flash_sale_settings.tracks_buyers() && max_per_wallet > 0 && flash_sale_settings.total_sold > 0 {
+            msg!("Cannot add a per-wallet limit after purchases were made");
+            return Err(ProgramError::InvalidArgument);
+        }
+        flash_sale_settings.max_per_wallet = max_per_wallet;
+    }
+    if let Some(paused) = args.paused {
+        flash_sale_settings.paused = paused;
+    }
+    if let Some(operator) = args.operator {
+        flash_sale_settings.operator_address = operator;
+    }
+    flash_sale_settings.check_terms()?;
+
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    emit_sale_updated(flash_sale_pda.key(), &flash_sale_settings)
+}
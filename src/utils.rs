@@ -1,4 +1,13 @@
-use pinocchio::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+use pinocchio::{
+    account_info::AccountInfo, cpi::{invoke_signed, slice_invoke_signed}, instruction::{AccountMeta, Instruction, Signer},
+    program_error::ProgramError, pubkey::Pubkey, ProgramResult,
+};
+use pinocchio::sysvars::{clock::Clock, Sysvar};
+use solana_program::pubkey::Pubkey as SPK;
+use spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use spl_token_2022::extension::{transfer_hook, BaseStateWithExtensions, StateWithExtensions};
+use spl_token_2022::state::Mint;
+use spl_transfer_hook_interface::onchain::add_extra_accounts_for_execute_cpi;
 
 pub fn check_owner(account: &AccountInfo, expected_owner: Pubkey) -> Result<(), ProgramError>{
     match *account.owner() == expected_owner{
@@ -20,4 +29,607 @@ pub fn check_address_is_any(account: &AccountInfo, allowed_addresses: &[Pubkey])
     } else {
         Err(ProgramError::IllegalOwner)
     }
-}
\ No newline at end of file
+}
+
+pub const BPS_DENOMINATOR: u16 = 10_000;
+
+// pinocchio only derives program addresses on chain, so off chain (in unit
+// tests) the same derivation goes through solana-program.
+pub fn find_program_address(seeds: &[&[u8]], program_id: &Pubkey) -> (Pubkey, u8) {
+    #[cfg(target_os = "solana")]
+    return pinocchio::pubkey::find_program_address(seeds, program_id);
+
+    #[cfg(not(target_os = "solana"))]
+    {
+        let (address, bump) = SPK::find_program_address(seeds, &SPK::new_from_array(*program_id));
+        (address.to_bytes(), bump)
+    }
+}
+
+const TOKEN_ACCOUNT_OWNER_OFFSET: usize = 32;
+const TOKEN_ACCOUNT_AMOUNT_OFFSET: usize = 64;
+
+pub fn check_token_account_owner(account: &AccountInfo, expected_owner: &Pubkey) -> Result<(), ProgramError>{
+    let data = account.try_borrow_data()?;
+    if data.len() < TOKEN_ACCOUNT_OWNER_OFFSET + 32 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    match data[TOKEN_ACCOUNT_OWNER_OFFSET..TOKEN_ACCOUNT_OWNER_OFFSET + 32] == expected_owner[..]{
+        true => Ok(()),
+        false => Err(ProgramError::IllegalOwner),
+    }
+}
+
+// Tokens sent on someone else's behalf go to their associated token account,
+// the one wallets look at.
+pub fn check_associated_token_account(
+    account: &AccountInfo,
+    wallet: &Pubkey,
+    mint: &AccountInfo,
+    token_program: &AccountInfo,
+) -> ProgramResult {
+    let expected_account = find_program_address(
+        &[wallet, token_program.key(), mint.key()],
+        &pinocchio_associated_token_account::id(),
+    );
+    match expected_account.0 == *account.key() {
+        true => Ok(()),
+        false => Err(ProgramError::InvalidSeeds),
+    }
+}
+
+pub fn get_token_account_amount(account: &AccountInfo) -> Result<u64, ProgramError>{
+    let data = account.try_borrow_data()?;
+    if data.len() < TOKEN_ACCOUNT_AMOUNT_OFFSET + 8 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(u64::from_le_bytes(data[TOKEN_ACCOUNT_AMOUNT_OFFSET..TOKEN_ACCOUNT_AMOUNT_OFFSET + 8].try_into().unwrap()))
+}
+
+const MINT_DECIMALS_OFFSET: usize = 44;
+
+pub fn get_mint_decimals(mint: &AccountInfo) -> Result<u8, ProgramError>{
+    let data = mint.try_borrow_data()?;
+    if data.len() <= MINT_DECIMALS_OFFSET {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(data[MINT_DECIMALS_OFFSET])
+}
+
+// Returns the fee the mint withholds on a transfer of `amount`, or None when
+// the mint has no TransferFeeConfig extension.
+pub fn get_transfer_fee(mint: &AccountInfo, amount: u64) -> Result<Option<u64>, ProgramError>{
+    if *mint.owner() != spl_token_2022::id().to_bytes() {
+        return Ok(None);
+    }
+    transfer_fee_for(&mint.try_borrow_data()?, Clock::get()?.epoch, amount)
+}
+
+// Fee a Token-2022 mint withholds on a transfer of `amount` in `epoch`, or
+// None when the mint has no transfer fee extension.
+pub fn transfer_fee_for(mint_data: &[u8], epoch: u64, amount: u64) -> Result<Option<u64>, ProgramError> {
+    let mint_state = StateWithExtensions::<Mint>::unpack(mint_data).map_err(|_| ProgramError::InvalidAccountData)?;
+    let fee_config = match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(fee_config) => fee_config,
+        Err(_) => return Ok(None),
+    };
+    let fee = fee_config
+        .calculate_epoch_fee(epoch, amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    Ok(Some(fee))
+}
+
+// Token-2022 keeps the fees withheld on transfers in the destination account,
+// and an account holding withheld fees cannot be closed. Moving them to the
+// mint is permissionless, so do it before closing one of our accounts.
+pub fn harvest_withheld_tokens(token_program: &AccountInfo, mint: &AccountInfo, account: &AccountInfo) -> ProgramResult {
+    if *mint.owner() != spl_token_2022::id().to_bytes() || transfer_fee_for(&mint.try_borrow_data()?, 0, 0)?.is_none() {
+        return Ok(());
+    }
+
+    let harvest_ix = spl_token_2022::extension::transfer_fee::instruction::harvest_withheld_tokens_to_mint(
+        &SPK::new_from_array(*token_program.key()),
+        &SPK::new_from_array(*mint.key()),
+        &[&SPK::new_from_array(*account.key())],
+    )
+    .map_err(|_| ProgramError::InvalidArgument)?;
+
+    let account_metas: Vec<AccountMeta> = harvest_ix
+        .accounts
+        .iter()
+        .map(|m| AccountMeta {
+            is_signer: m.is_signer,
+            is_writable: m.is_writable,
+            pubkey: m.pubkey.as_array(),
+        })
+        .collect();
+
+    let new_ix: Instruction<'_, '_, '_, '_> = Instruction {
+        program_id: &harvest_ix.program_id.to_bytes(),
+        data: &harvest_ix.data,
+        accounts: &account_metas,
+    };
+
+    invoke_signed(&new_ix, &[mint, account, token_program], &[])
+}
+
+pub fn get_transfer_hook_program(mint: &AccountInfo) -> Result<Option<SPK>, ProgramError>{
+    if *mint.owner() != spl_token_2022::id().to_bytes() {
+        return Ok(None);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<Mint>::unpack(&data).map_err(|_| ProgramError::InvalidAccountData)?;
+    Ok(transfer_hook::get_program_id(&mint_state))
+}
+
+fn find_account<'a>(accounts: &'a [AccountInfo], key: &SPK) -> Result<&'a AccountInfo, ProgramError>{
+    accounts
+        .iter()
+        .find(|account| account.key() == key.as_array())
+        .ok_or(ProgramError::NotEnoughAccountKeys)
+}
+
+// Owned copy of an account, so the solana-program based resolution helpers of
+// the transfer hook interface can read it without borrowing it unchecked.
+pub(crate) struct AccountSnapshot {
+    pub key: SPK,
+    pub owner: SPK,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+}
+
+impl AccountSnapshot {
+    fn of(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Ok(AccountSnapshot {
+            key: SPK::new_from_array(*account.key()),
+            owner: SPK::new_from_array(*account.owner()),
+            lamports: account.lamports(),
+            data: account.try_borrow_data()?.to_vec(),
+        })
+    }
+
+    fn info(&mut self) -> solana_program::account_info::AccountInfo<'_> {
+        solana_program::account_info::AccountInfo::new(
+            &self.key,
+            false,
+            false,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            false,
+            0,
+        )
+    }
+}
+
+// Appends the hook's extra accounts, its validation account and the hook
+// program to `transfer_ix`, in the order Token-2022 expects. `accounts` are
+// the transfer's source, mint, destination and authority followed by the
+// accounts the client passed for the hook.
+pub(crate) fn resolve_transfer_hook_accounts(
+    transfer_ix: &mut solana_program::instruction::Instruction,
+    accounts: &mut [AccountSnapshot],
+    hook_program: &SPK,
+    amount: u64,
+) -> ProgramResult {
+    let mut infos: Vec<_> = accounts.iter_mut().map(AccountSnapshot::info).collect();
+    if infos.len() < 4 {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let additional_accounts = infos.split_off(4);
+    let [source, mint, destination, authority]: [_; 4] =
+        infos.try_into().map_err(|_| ProgramError::NotEnoughAccountKeys)?;
+
+    add_extra_accounts_for_execute_cpi(
+        transfer_ix,
+        &mut Vec::new(),
+        hook_program,
+        source,
+        mint,
+        destination,
+        authority,
+        amount,
+        &additional_accounts,
+    )
+    .map_err(|err| ProgramError::from(u64::from(err)))
+}
+
+fn add_transfer_hook_accounts<'a>(
+    transfer_ix: &mut solana_program::instruction::Instruction,
+    account_infos: &mut Vec<&'a AccountInfo>,
+    hook_program: &SPK,
+    amount: u64,
+    hook_accounts: &'a [AccountInfo],
+) -> ProgramResult {
+    let mut accounts = account_infos[..4]
+        .iter()
+        .copied()
+        .chain(hook_accounts)
+        .map(AccountSnapshot::of)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let transfer_accounts = transfer_ix.accounts.len();
+    resolve_transfer_hook_accounts(transfer_ix, &mut accounts, hook_program, amount)?;
+    for meta in &transfer_ix.accounts[transfer_accounts..] {
+        account_infos.push(find_account(hook_accounts, &meta.pubkey)?);
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_tokens(
+    token_program: &AccountInfo,
+    from: &AccountInfo,
+    mint: &AccountInfo,
+    to: &AccountInfo,
+    authority: &AccountInfo,
+    amount: u64,
+    decimals: u8,
+    hook_accounts: &[AccountInfo],
+    signers: &[Signer],
+) -> ProgramResult {
+    let mut transfer_ix = match get_transfer_fee(mint, amount)? {
+        Some(fee) => spl_token_2022::extension::transfer_fee::instruction::transfer_checked_with_fee(
+            &SPK::new_from_array(*token_program.key()),
+            &SPK::new_from_array(*from.key()),
+            &SPK::new_from_array(*mint.key()),
+            &SPK::new_from_array(*to.key()),
+            &SPK::new_from_array(*authority.key()),
+            &[],
+            amount,
+            decimals,
+            fee,
+        ),
+        None => spl_token_2022::instruction::transfer_checked(
+            &SPK::new_from_array(*token_program.key()),
+            &SPK::new_from_array(*from.key()),
+            &SPK::new_from_array(*mint.key()),
+            &SPK::new_from_array(*to.key()),
+            &SPK::new_from_array(*authority.key()),
+            &[],
+            amount,
+            decimals,
+        ),
+    }
+    .unwrap();
+
+    let mut account_infos = vec![from, mint, to, authority];
+    if let Some(hook_program) = get_transfer_hook_program(mint)? {
+        add_transfer_hook_accounts(&mut transfer_ix, &mut account_infos, &hook_program, amount, hook_accounts)?;
+    }
+    account_infos.push(token_program);
+
+    let account_metas: Vec<AccountMeta> = transfer_ix
+        .accounts
+        .iter()
+        .map(|m| AccountMeta {
+            is_signer: m.is_signer,
+            is_writable: m.is_writable,
+            pubkey: m.pubkey.as_array(),
+        })
+        .collect();
+
+    let new_ix: Instruction<'_, '_, '_, '_> = Instruction {
+        program_id: &transfer_ix.program_id.to_bytes(),
+        data: &transfer_ix.data,
+        accounts: &account_metas,
+    };
+
+    slice_invoke_signed(&new_ix, &account_infos, signers)
+}
+
+pub fn close_token_account(
+    token_program: &AccountInfo,
+    account: &AccountInfo,
+    destination: &AccountInfo,
+    authority: &AccountInfo,
+    signers: &[Signer],
+) -> ProgramResult {
+    let close_ix = spl_token_2022::instruction::close_account(
+        &SPK::new_from_array(*token_program.key()),
+        &SPK::new_from_array(*account.key()),
+        &SPK::new_from_array(*destination.key()),
+        &SPK::new_from_array(*authority.key()),
+        &[],
+    )
+    .unwrap();
+
+    let account_metas: Vec<AccountMeta> = close_ix
+        .accounts
+        .iter()
+        .map(|m| AccountMeta {
+            is_signer: m.is_signer,
+            is_writable: m.is_writable,
+            pubkey: m.pubkey.as_array(),
+        })
+        .collect();
+
+    let new_ix: Instruction<'_, '_, '_, '_> = Instruction {
+        program_id: &close_ix.program_id.to_bytes(),
+        data: &close_ix.data,
+        accounts: &account_metas,
+    };
+
+    invoke_signed(&new_ix, &[account, destination, authority, token_program], signers)
+}
+
+pub fn deinit_account_if_exists(account: &AccountInfo, receiver: &AccountInfo) -> ProgramResult {
+    let lamports = *account.try_borrow_lamports().unwrap();
+    if lamports == 0 {
+        return Ok(());
+    }
+
+    *account.try_borrow_mut_lamports().unwrap() = 0;
+    *receiver.try_borrow_mut_lamports().unwrap() += lamports;
+
+    account.try_borrow_mut_data()?.fill(0);
+    account.resize(0)?;
+
+    // The system program can only reassign accounts it owns, so the sale
+    // program hands the emptied account back itself.
+    unsafe {
+        account.assign(&pinocchio_system::id());
+    }
+
+    Ok(())
+}
+
+pub fn read_bytes<'a>(data: &'a [u8], offset: &mut usize, len: usize) -> Result<&'a [u8], String> {
+    let value = data.get(*offset..offset.saturating_add(len)).ok_or("Unexpected end of data")?;
+    *offset += len;
+    Ok(value)
+}
+
+pub fn read_pubkey(data: &[u8], offset: &mut usize) -> Result<Pubkey, String> {
+    Ok(read_bytes(data, offset, 32)?.try_into().unwrap())
+}
+
+pub fn read_u64(data: &[u8], offset: &mut usize) -> Result<u64, String> {
+    Ok(u64::from_le_bytes(read_bytes(data, offset, 8)?.try_into().unwrap()))
+}
+
+pub fn read_i64(data: &[u8], offset: &mut usize) -> Result<i64, String> {
+    Ok(i64::from_le_bytes(read_bytes(data, offset, 8)?.try_into().unwrap()))
+}
+
+pub fn read_u32(data: &[u8], offset: &mut usize) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(read_bytes(data, offset, 4)?.try_into().unwrap()))
+}
+
+pub fn read_u16(data: &[u8], offset: &mut usize) -> Result<u16, String> {
+    Ok(u16::from_le_bytes(read_bytes(data, offset, 2)?.try_into().unwrap()))
+}
+
+pub fn read_u8(data: &[u8], offset: &mut usize) -> Result<u8, String> {
+    Ok(read_bytes(data, offset, 1)?[0])
+}
+
+// A u32 length followed by that many bytes of UTF-8.
+pub fn read_string(data: &[u8], offset: &mut usize) -> Result<String, String> {
+    let len = read_u32(data, offset)? as usize;
+    String::from_utf8(read_bytes(data, offset, len)?.to_vec()).map_err(|_| "String is not valid UTF-8".to_string())
+}
+
+pub fn read_option<'a>(data: &'a [u8], offset: &mut usize, len: usize) -> Result<Option<&'a [u8]>, String> {
+    let tag = *data.get(*offset).ok_or("Unexpected end of data")?;
+    *offset += 1;
+    match tag {
+        0 => Ok(None),
+        1 => {
+            let value = data.get(*offset..*offset + len).ok_or("Unexpected end of data")?;
+            *offset += len;
+            Ok(Some(value))
+        }
+        _ => Err("Invalid option tag".to_string()),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use core::mem::MaybeUninit;
+    use pinocchio::account_info::MAX_PERMITTED_DATA_INCREASE;
+    use pinocchio::entrypoint::{deserialize, NON_DUP_MARKER};
+    use pinocchio::MAX_TX_ACCOUNTS;
+    use spl_tlv_account_resolution::account::ExtraAccountMeta;
+    use spl_tlv_account_resolution::seeds::Seed;
+    use spl_tlv_account_resolution::state::ExtraAccountMetaList;
+    use spl_token_2022::extension::{BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut};
+    use spl_transfer_hook_interface::get_extra_account_metas_address;
+    use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+
+    // An account as a transaction passes it to the program.
+    pub struct TestAccount {
+        pub key: Pubkey,
+        pub owner: Pubkey,
+        pub lamports: u64,
+        pub data: Vec<u8>,
+        pub is_signer: bool,
+    }
+
+    impl TestAccount {
+        pub fn new(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> Self {
+            TestAccount { key, owner, lamports: 1_000_000_000, data, is_signer: false }
+        }
+
+        pub fn wallet(key: Pubkey) -> Self {
+            Self::new(key, pinocchio_system::id(), Vec::new())
+        }
+
+        pub fn signer(self) -> Self {
+            TestAccount { is_signer: true, ..self }
+        }
+    }
+
+    // Accounts laid out the way the runtime serializes them for the
+    // entrypoint, so handlers can run off chain. CPIs are no-ops off chain:
+    // token balances never move, lamports moved by the program itself do.
+    pub struct TestAccounts {
+        _input: Vec<u64>,
+        pub infos: Vec<AccountInfo>,
+    }
+
+    pub fn test_accounts(accounts: &[TestAccount]) -> TestAccounts {
+        let mut input = (accounts.len() as u64).to_le_bytes().to_vec();
+        for account in accounts {
+            input.extend_from_slice(&[NON_DUP_MARKER, account.is_signer as u8, 1, 0, 0, 0, 0, 0]);
+            input.extend_from_slice(&account.key);
+            input.extend_from_slice(&account.owner);
+            input.extend_from_slice(&account.lamports.to_le_bytes());
+            input.extend_from_slice(&(account.data.len() as u64).to_le_bytes());
+            input.extend_from_slice(&account.data);
+            input.resize((input.len() + MAX_PERMITTED_DATA_INCREASE).next_multiple_of(8), 0);
+            input.extend_from_slice(&0u64.to_le_bytes());
+        }
+        input.extend_from_slice(&0u64.to_le_bytes());
+        input.extend_from_slice(&crate::id());
+
+        let mut aligned = vec![0u64; input.len().div_ceil(8)];
+        let bytes = aligned.as_mut_ptr() as *mut u8;
+        let mut infos = [const { MaybeUninit::<AccountInfo>::uninit() }; MAX_TX_ACCOUNTS];
+        // SAFETY: `aligned` holds a serialized input and outlives the infos
+        // pointing into it.
+        let count = unsafe {
+            bytes.copy_from_nonoverlapping(input.as_ptr(), input.len());
+            deserialize(bytes, &mut infos).1
+        };
+        TestAccounts {
+            _input: aligned,
+            infos: infos[..count].iter().map(|info| unsafe { info.assume_init() }).collect(),
+        }
+    }
+
+    pub fn program_address(seeds: &[&[u8]]) -> Pubkey {
+        find_program_address(seeds, &crate::id()).0
+    }
+
+    // SPL token account data holding `amount` of `mint` for `owner`.
+    pub fn token_account_data(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Vec<u8> {
+        let mut data = vec![0; TOKEN_ACCOUNT_LEN];
+        data[..32].copy_from_slice(mint);
+        data[TOKEN_ACCOUNT_OWNER_OFFSET..TOKEN_ACCOUNT_OWNER_OFFSET + 32].copy_from_slice(owner);
+        data[TOKEN_ACCOUNT_AMOUNT_OFFSET..TOKEN_ACCOUNT_AMOUNT_OFFSET + 8].copy_from_slice(&amount.to_le_bytes());
+        data[TOKEN_ACCOUNT_STATE_OFFSET] = 1;
+        data
+    }
+
+    const TOKEN_ACCOUNT_LEN: usize = 165;
+    const TOKEN_ACCOUNT_STATE_OFFSET: usize = 108;
+
+    // Token-2022 mint data, with a TransferFeeConfig when `fee_bps` is given.
+    pub fn mint_data(fee_bps: Option<u16>, maximum_fee: u64) -> Vec<u8> {
+        let extensions = match fee_bps {
+            Some(_) => vec![ExtensionType::TransferFeeConfig],
+            None => vec![],
+        };
+        let mut data = vec![0; ExtensionType::try_calculate_account_len::<Mint>(&extensions).unwrap()];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        if let Some(fee_bps) = fee_bps {
+            let fee_config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            fee_config.newer_transfer_fee.transfer_fee_basis_points = fee_bps.into();
+            fee_config.newer_transfer_fee.maximum_fee = maximum_fee.into();
+            fee_config.older_transfer_fee = fee_config.newer_transfer_fee;
+        }
+        state.base.decimals = 6;
+        state.base.is_initialized = true;
+        state.pack_base();
+        if fee_bps.is_some() {
+            state.init_account_type().unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn transfer_fee_comes_from_the_mint_extension() {
+        let fee_mint = mint_data(Some(100), 1_000);
+        assert_eq!(transfer_fee_for(&fee_mint, 0, 1_000), Ok(Some(10)));
+        // Rounds up, and is capped at the maximum fee.
+        assert_eq!(transfer_fee_for(&fee_mint, 0, 1_001), Ok(Some(11)));
+        assert_eq!(transfer_fee_for(&fee_mint, 0, 1_000_000_000), Ok(Some(1_000)));
+
+        assert_eq!(transfer_fee_for(&mint_data(None, 0), 0, 1_000), Ok(None));
+        assert!(transfer_fee_for(&[0; 10], 0, 1_000).is_err());
+    }
+
+    fn snapshot(key: SPK, data: Vec<u8>) -> AccountSnapshot {
+        AccountSnapshot {
+            key,
+            owner: SPK::new_from_array([0; 32]),
+            lamports: 0,
+            data,
+        }
+    }
+
+    #[test]
+    fn hook_accounts_follow_the_validation_account_metas() {
+        let hook_program = SPK::new_from_array([7; 32]);
+        let [source, mint, destination, authority, fixed] = [1, 2, 3, 4, 5].map(|byte| SPK::new_from_array([byte; 32]));
+        let seeded = SPK::find_program_address(&[b"counter", source.as_ref()], &hook_program).0;
+        let validation = get_extra_account_metas_address(&mint, &hook_program);
+
+        let extra_metas = [
+            ExtraAccountMeta::new_with_pubkey(&fixed, false, true).unwrap(),
+            ExtraAccountMeta::new_with_seeds(
+                &[
+                    Seed::Literal { bytes: b"counter".to_vec() },
+                    Seed::AccountKey { index: 0 },
+                ],
+                false,
+                true,
+            )
+            .unwrap(),
+        ];
+        let mut validation_data = vec![0; ExtraAccountMetaList::size_of(extra_metas.len()).unwrap()];
+        ExtraAccountMetaList::init::<ExecuteInstruction>(&mut validation_data, &extra_metas).unwrap();
+
+        let mut transfer_ix = spl_token_2022::instruction::transfer_checked(
+            &spl_token_2022::id(),
+            &source,
+            &mint,
+            &destination,
+            &authority,
+            &[],
+            1_000,
+            6,
+        )
+        .unwrap();
+        // Hook accounts may come in any order; they are looked up by address.
+        let mut accounts = vec![
+            snapshot(source, vec![]),
+            snapshot(mint, vec![]),
+            snapshot(destination, vec![]),
+            snapshot(authority, vec![]),
+            snapshot(hook_program, vec![]),
+            snapshot(seeded, vec![]),
+            snapshot(validation, validation_data),
+            snapshot(fixed, vec![]),
+        ];
+        resolve_transfer_hook_accounts(&mut transfer_ix, &mut accounts, &hook_program, 1_000).unwrap();
+
+        let keys: Vec<SPK> = transfer_ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys, [source, mint, destination, authority, fixed, seeded, validation, hook_program]);
+        assert!(transfer_ix.accounts[4].is_writable && transfer_ix.accounts[5].is_writable);
+        assert!(!transfer_ix.accounts[6].is_writable && !transfer_ix.accounts[7].is_writable);
+    }
+
+    #[test]
+    fn missing_hook_accounts_are_an_error() {
+        let hook_program = SPK::new_from_array([7; 32]);
+        let mut transfer_ix = spl_token_2022::instruction::transfer_checked(
+            &spl_token_2022::id(),
+            &SPK::new_from_array([1; 32]),
+            &SPK::new_from_array([2; 32]),
+            &SPK::new_from_array([3; 32]),
+            &SPK::new_from_array([4; 32]),
+            &[],
+            1_000,
+            6,
+        )
+        .unwrap();
+        let mut accounts: Vec<AccountSnapshot> =
+            (1..=4).map(|byte| snapshot(SPK::new_from_array([byte; 32]), vec![])).collect();
+
+        assert!(resolve_transfer_hook_accounts(&mut transfer_ix, &mut accounts, &hook_program, 1_000).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::sysvars::clock::Clock;
+use pinocchio::sysvars::Sysvar;
+use pinocchio::{msg, ProgramResult};
+
+use crate::error::FlashSaleError;
+use crate::events::{emit_withdrawal, WithdrawalKind};
+use crate::init_flash_sale::FlashSale;
+use crate::proceeds_vault::{pay_out, PaymentTokenAccounts};
+use crate::revenue_split::distribute_proceeds;
+use crate::init_config::load_config;
+use crate::utils::{check_address, check_owner, check_token_account_owner};
+
+fn release_held_fees(
+    flash_sale_pda: &AccountInfo,
+    proceeds_vault_pda: &AccountInfo,
+    config_pda: &AccountInfo,
+    fee_recipient: &AccountInfo,
+    payment_accounts: Option<&PaymentTokenAccounts>,
+    amount: u64,
+    now: i64,
+) -> ProgramResult {
+    let config = load_config(config_pda)?.ok_or(ProgramError::UninitializedAccount)?;
+    match payment_accounts {
+        Some(_) => check_token_account_owner(fee_recipient, &config.fee_recipient)?,
+        None => check_address(fee_recipient, config.fee_recipient)?,
+    }
+
+    pay_out(flash_sale_pda, proceeds_vault_pda, fee_recipient, payment_accounts, amount)?;
+    emit_withdrawal(flash_sale_pda.key(), WithdrawalKind::ProtocolFees, fee_recipient.key(), amount, now)
+}
+
+pub fn withdraw_proceeds(accounts: &[AccountInfo], _instruction_data: &[u8]) -> ProgramResult {
+    withdraw_proceeds_at(accounts, Clock::get()?.unix_timestamp)
+}
+
+fn withdraw_proceeds_at(accounts: &[AccountInfo], now: i64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let authority = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+    let proceeds_vault_pda = accounts_iter.next().unwrap();
+    let receiver = accounts_iter.next().unwrap();
+
+    if !authority.is_signer(){
+        msg!("Authority must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.authority_address != *authority.key(){
+        msg!("Unexpected flash sale authority");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+
+    if !flash_sale_settings.proceeds_unlocked(now) {
+        msg!("Proceeds cannot be withdrawn yet");
+        return Err(FlashSaleError::WithdrawLocked.into());
+    }
+
+    let available = flash_sale_settings
+        .total_proceeds
+        .saturating_sub(flash_sale_settings.withdrawn_proceeds);
+    let held_fees = flash_sale_settings.held_protocol_fees;
+
+    flash_sale_settings.withdrawn_proceeds = flash_sale_settings.total_proceeds;
+    flash_sale_settings.held_protocol_fees = 0;
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    let payment_accounts = PaymentTokenAccounts::from_iter(&flash_sale_settings, accounts_iter)?;
+    // Soft-cap sales pass the config and fee recipient for the protocol fees
+    // held back until the cap was reached; other sales pass the program id.
+    let config_pda = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let fee_recipient = accounts_iter.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+    if held_fees > 0 {
+        release_held_fees(
+            flash_sale_pda,
+            proceeds_vault_pda,
+            config_pda,
+            fee_recipient,
+            payment_accounts.as_ref(),
+            held_fees,
+            now,
+        )?;
+    }
+    if available == 0 {
+        return Ok(());
+    }
+
+    if !flash_sale_settings.payees().is_empty() {
+        return distribute_proceeds(
+            &flash_sale_settings,
+            flash_sale_pda,
+            proceeds_vault_pda,
+            payment_accounts.as_ref(),
+            accounts_iter,
+            available,
+            now,
+        );
+    }
+    pay_out(
+        flash_sale_pda,
+        proceeds_vault_pda,
+        receiver,
+        payment_accounts.as_ref(),
+        available,
+    )?;
+
+    emit_withdrawal(flash_sale_pda.key(), WithdrawalKind::Proceeds, receiver.key(), available, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init_config::{Config, CONFIG_ACCOUNT_SIZE};
+    use crate::init_flash_sale::tests::{sale, sale_data};
+    use crate::proceeds_vault::ProceedsWithdrawRule;
+    use crate::utils::tests::{program_address, test_accounts, TestAccount};
+    use pinocchio::pubkey::Pubkey;
+
+    const SALE: Pubkey = [5; 32];
+    const FEE_RECIPIENT: Pubkey = [9; 32];
+
+    // A SOL sale that ended at 2_000 and raised 2_500 for its seller, paid
+    // out once it ends.
+    fn ended_sale() -> FlashSale {
+        FlashSale { withdraw_rule: ProceedsWithdrawRule::AfterSaleEnd, total_proceeds: 2_500, ..sale("Ticket") }
+    }
+
+    fn config_data() -> Vec<u8> {
+        let config = Config { admin: [1; 32], protocol_fee_bps: 100, fee_recipient: FEE_RECIPIENT, paused: false };
+        let mut data = vec![0; CONFIG_ACCOUNT_SIZE];
+        config.write_to_slice(&mut data).unwrap();
+        data
+    }
+
+    fn withdraw_accounts(sale: &FlashSale) -> Vec<TestAccount> {
+        let mut accounts = vec![
+            TestAccount::wallet(sale.authority_address).signer(),
+            TestAccount::new(SALE, crate::id(), sale_data(sale)),
+            TestAccount::new(program_address(&[b"proceeds", &SALE]), crate::id(), Vec::new()),
+            TestAccount::wallet([4; 32]),
+        ];
+        accounts.extend((0..3).map(|_| TestAccount::wallet(crate::id())));
+        accounts.push(TestAccount::new(program_address(&[b"config"]), crate::id(), config_data()));
+        accounts.push(TestAccount::wallet(FEE_RECIPIENT));
+        accounts
+    }
+
+    fn run(accounts: &[TestAccount], now: i64) -> Option<ProgramError> {
+        withdraw_proceeds_at(&test_accounts(accounts).infos, now).err()
+    }
+
+    #[test]
+    fn proceeds_are_withdrawn_once() {
+        let accounts = test_accounts(&withdraw_accounts(&ended_sale()));
+        let accounts = &accounts.infos;
+        withdraw_proceeds_at(accounts, 2_500).unwrap();
+        withdraw_proceeds_at(accounts, 2_600).unwrap();
+
+        assert_eq!(accounts[3].lamports(), 1_000_002_500);
+        assert_eq!(accounts[2].lamports(), 999_997_500);
+        let withdrawn = FlashSale::try_from(accounts[1].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!(withdrawn.withdrawn_proceeds, 2_500);
+    }
+
+    #[test]
+    fn fees_held_for_a_reached_soft_cap_go_to_the_fee_recipient() {
+        let sale = FlashSale { soft_cap: 2_000, held_protocol_fees: 25, ..ended_sale() };
+        let accounts = test_accounts(&withdraw_accounts(&sale));
+        let accounts = &accounts.infos;
+        withdraw_proceeds_at(accounts, 2_500).unwrap();
+
+        assert_eq!(accounts[8].lamports(), 1_000_000_025);
+        assert_eq!(accounts[3].lamports(), 1_000_002_500);
+        let withdrawn = FlashSale::try_from(accounts[1].try_borrow_data().unwrap().as_ref()).unwrap();
+        assert_eq!(withdrawn.held_protocol_fees, 0);
+
+        let mut other_recipient = withdraw_accounts(&sale);
+        other_recipient[8].key = [4; 32];
+        assert_eq!(run(&other_recipient, 2_500), Some(ProgramError::IllegalOwner));
+
+        let mut no_config = withdraw_accounts(&sale);
+        no_config[7].owner = pinocchio_system::id();
+        assert_eq!(run(&no_config, 2_500), Some(ProgramError::UninitializedAccount));
+    }
+
+    #[test]
+    fn withdrawals_check_the_authority_and_the_unlock() {
+        let sale = ended_sale();
+
+        let mut unsigned = withdraw_accounts(&sale);
+        unsigned[0] = TestAccount::wallet(sale.authority_address);
+        assert_eq!(run(&unsigned, 2_500), Some(ProgramError::MissingRequiredSignature));
+
+        let mut stranger = withdraw_accounts(&sale);
+        stranger[0] = TestAccount::wallet([8; 32]).signer();
+        assert_eq!(run(&stranger, 2_500), Some(FlashSaleError::Unauthorized.into()));
+
+        let mut forged_sale = withdraw_accounts(&sale);
+        forged_sale[1].owner = [8; 32];
+        assert_eq!(run(&forged_sale, 2_500), Some(ProgramError::IllegalOwner));
+
+        let mut other_vault = withdraw_accounts(&sale);
+        other_vault[2].key = program_address(&[b"proceeds", &[8; 32]]);
+        assert_eq!(run(&other_vault, 2_500), Some(ProgramError::InvalidSeeds));
+
+        assert_eq!(run(&withdraw_accounts(&sale), 1_999), Some(FlashSaleError::WithdrawLocked.into()));
+        let missed_soft_cap = FlashSale { soft_cap: 5_000, ..sale };
+        assert_eq!(run(&withdraw_accounts(&missed_soft_cap), 2_500), Some(FlashSaleError::WithdrawLocked.into()));
+    }
+}
@@ -0,0 +1,100 @@
+use pinocchio::instruction::Signer;
+use pinocchio::sysvars::clock::Clock;
+use pinocchio::sysvars::Sysvar;
+
+use pinocchio::account_info::AccountInfo;
+use pinocchio::program_error::ProgramError;
+use pinocchio::{msg, seeds, ProgramResult};
+
+use crate::error::FlashSaleError;
+use crate::events::{emit_sale_updated, emit_withdrawal, WithdrawalKind};
+use crate::get_token::GetTokenArgs;
+use crate::init_flash_sale::FlashSale;
+use crate::utils::{check_address_is_any, check_owner, find_program_address, get_mint_decimals, transfer_tokens};
+
+pub fn withdraw_unsold(accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let authority = accounts_iter.next().unwrap();
+    let receiver_token_ata = accounts_iter.next().unwrap();
+    let token_mint = accounts_iter.next().unwrap();
+    let token_deposit_pda = accounts_iter.next().unwrap();
+    let token_deposit_ata = accounts_iter.next().unwrap();
+    let flash_sale_pda = accounts_iter.next().unwrap();
+    let token_program = accounts_iter.next().unwrap();
+    let hook_accounts = accounts_iter.as_slice();
+
+    let args = GetTokenArgs::try_from(instruction_data).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    if !authority.is_signer(){
+        msg!("Authority must be signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_address_is_any(token_program, &[pinocchio_token::id(), spl_token_2022::id().to_bytes()])?;
+    check_owner(flash_sale_pda, crate::id())?;
+
+    let mut flash_sale_data = flash_sale_pda.try_borrow_mut_data()?;
+    let mut flash_sale_settings = FlashSale::try_from(flash_sale_data.as_ref())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if flash_sale_settings.mint_address != *token_mint.key(){
+        msg!("Unexpected token mint address");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if flash_sale_settings.authority_address != *authority.key(){
+        msg!("Unexpected flash sale authority");
+        return Err(FlashSaleError::Unauthorized.into());
+    }
+
+    let clock = Clock::get()?;
+    if flash_sale_settings.lock_withdrawals && flash_sale_settings.is_active(clock.unix_timestamp){
+        msg!("Withdrawals are locked until the sale ends");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if flash_sale_settings.supply_frozen(clock.unix_timestamp) {
+        msg!("Commit sale supply is fixed once the sale ends");
+        return Err(FlashSaleError::SaleEnded.into());
+    }
+
+    let unsold = flash_sale_settings
+        .total_supply
+        .saturating_sub(flash_sale_settings.reserved_supply());
+    if args.amount > unsold {
+        msg!("Not enough unsold tokens");
+        return Err(ProgramError::InsufficientFunds);
+    }
+    flash_sale_settings.total_supply -= args.amount;
+    flash_sale_settings.write_to_slice(&mut flash_sale_data)?;
+
+    let seed = flash_sale_settings.seed();
+    let id_seed = flash_sale_settings.id_seed();
+    let expected_deposit_account = find_program_address(
+        &[b"deposit", &seed, token_mint.key(), &flash_sale_settings.owner_address, &id_seed],
+        &crate::id(),
+    );
+
+    let deposit_binding = [expected_deposit_account.1];
+    let deposit_seeds = seeds!(
+        b"deposit",
+        seed.as_slice(),
+        token_mint.key(),
+        &flash_sale_settings.owner_address,
+        id_seed.as_slice(),
+        &deposit_binding
+    );
+
+    transfer_tokens(
+        token_program,
+        token_deposit_ata,
+        token_mint,
+        receiver_token_ata,
+        token_deposit_pda,
+        args.amount,
+        get_mint_decimals(token_mint)?,
+        hook_accounts,
+        &[Signer::from(&deposit_seeds)],
+    )?;
+
+    emit_withdrawal(flash_sale_pda.key(), WithdrawalKind::UnsoldTokens, receiver_token_ata.key(), args.amount, clock.unix_timestamp)?;
+    emit_sale_updated(flash_sale_pda.key(), &flash_sale_settings)
+}